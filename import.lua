@@ -1,6 +1,12 @@
 local cycleway = osm2pgsql.define_way_table("cycleway_way", {{
     column = 'name',
     type = 'text'
+}, {
+    column = 'winter_service',
+    type = 'text'
+}, {
+    column = 'seasonal',
+    type = 'text'
 }, {
     column = 'geom',
     type = 'LineString',
@@ -381,6 +387,8 @@ function osm2pgsql.process_way(object)
             source = object.nodes[1],
             target = object.nodes[#object.nodes],
             kind = 'cycleway',
+            winter_service = object.tags.winter_service,
+            seasonal = object.tags.seasonal,
             tags = object.tags,
             nodes = "{" .. table.concat(object.nodes, ",") .. "}"
         })
@@ -394,6 +402,8 @@ function osm2pgsql.process_way(object)
             source = object.nodes[1],
             target = object.nodes[#object.nodes],
             kind = 'designated',
+            winter_service = object.tags.winter_service,
+            seasonal = object.tags.seasonal,
             tags = object.tags,
             nodes = " {" .. table.concat(object.nodes, ",") .. "}"
         })
@@ -406,6 +416,8 @@ function osm2pgsql.process_way(object)
             source = object.nodes[1],
             target = object.nodes[#object.nodes],
             kind = 'shared_lane',
+            winter_service = object.tags.winter_service,
+            seasonal = object.tags.seasonal,
             tags = object.tags,
             nodes = "{" .. table.concat(object.nodes, ",") .. "}"
         })
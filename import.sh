@@ -4,6 +4,13 @@ wget https://download.geofabrik.de/north-america/canada/quebec-latest.osm.pbf -O
 
 osm2pgsql -H db -U postgres -d carte -O flex -S import.lua quebec-latest.osm.pbf
 
+# the freshness of a score halves every SCORE_HALF_LIFE_DAYS since it was
+# given or last confirmed, the old scores fade back toward the tags
+SCORE_HALF_LIFE_DAYS=${SCORE_HALF_LIFE_DAYS:-365}
+# each net vote of no longer accurate counts as one more half life
+SCORE_FRESHNESS="power(0.5, extract(epoch from now() - coalesce(cs.confirmed_at, cs.created_at)) / 86400 / $SCORE_HALF_LIFE_DAYS + greatest(votes.net_not_valid, 0))"
+SCORE_VOTES="left join lateral (select count(*) filter (where not v.still_valid) - count(*) filter (where v.still_valid) as net_not_valid from score_vote v where v.cyclability_score_id = cs.id) votes on true"
+
 psql -h db -U postgres -d carte -c "
                                     drop materialized view if exists bike_path;
                                     CREATE MATERIALIZED VIEW bike_path AS
@@ -18,12 +25,17 @@ psql -h db -U postgres -d carte -c "
                                                 case
                                                     when score is null then -1
                                                     else score
-                                                end as score
+                                                end as score,
+                                                coalesce(freshness, 1) as freshness,
+                                                -- the tiles have no arrays, the map tests the issues with in
+                                                array_to_string(issues, ',') as issues
                                             FROM (
-                                                SELECT c.*, cs.score,
+                                                SELECT c.*, cs.score, cs.issues,
+                                                $SCORE_FRESHNESS as freshness,
                                                 ROW_NUMBER() OVER (PARTITION BY c.way_id ORDER BY cs.created_at DESC) as rn
                                                 FROM cycleway_way c 
-                                                LEFT JOIN cyclability_score cs ON c.way_id = ANY(cs.way_ids)
+                                                LEFT JOIN cyclability_score cs ON c.way_id = ANY(cs.way_ids) AND NOT cs.hidden
+                                                $SCORE_VOTES
                                             ) t
                                         WHERE t.rn = 1;
                                     CREATE UNIQUE INDEX bike_path_way_id_idx ON bike_path(way_id);
@@ -32,12 +44,16 @@ psql -h db -U postgres -d carte -c "
                                     drop materialized view if exists last_cycleway_score cascade;
                                     CREATE MATERIALIZED VIEW last_cycleway_score
                                     AS
-                                        SELECT *
+                                        -- the score moves toward 1, no effect on the cost, as it gets old
+                                        SELECT t.*, 1 - freshness * (1 - score) as decayed_score
                                             FROM (
-                                                SELECT c.*, cs.score,
+                                                SELECT c.*, cs.score, cs.closed_from, cs.closed_until, cs.not_cleared,
+                                                $SCORE_FRESHNESS as freshness,
                                                 ROW_NUMBER() OVER (PARTITION BY c.way_id ORDER BY cs.created_at DESC) as rn
                                                 FROM cyclability_score cs 
                                                 JOIN cycleway_way c ON c.way_id = ANY(cs.way_ids)
+                                                $SCORE_VOTES
+                                                WHERE NOT cs.hidden
                                             ) t
                                         WHERE t.rn = 1;
                                     CREATE UNIQUE INDEX last_cycleway_score_way_id_idx ON last_cycleway_score(way_id);
@@ -97,7 +113,13 @@ psql -h db -U postgres -d carte -c "
 
                                     drop materialized view if exists edge;
                                     CREATE MATERIALIZED VIEW edge 
-                                    AS SELECT  
+                                    AS SELECT
+                                        e.*,
+                                        base_cost *
+                                        (1 + 10 * greatest(coalesce((z2 - z1) / nullif(length_m, 0), 0), 0)) as cost,
+                                        base_reverse_cost *
+                                        (1 + 10 * greatest(coalesce((z1 - z2) / nullif(length_m, 0), 0), 0)) as reverse_cost
+                                    FROM (SELECT  
                                         id,
                                         node as source,
                                         awe.nodes[(segment).path[1]+1] as target,
@@ -106,31 +128,70 @@ psql -h db -U postgres -d carte -c "
                                         st_x(st_transform(ST_PointN((segment).geom, 2), 4326)) as x2,
                                         st_y(st_transform(ST_PointN((segment).geom, 2), 4326)) as y2,
                                         awe.way_id,
+                                        awe.tags,
                                         score,
+                                        decayed_score,
+                                        closed_from,
+                                        closed_until,
                                         (segment).geom,
+                                        -- applied from december to march, the network cleared of snow is preferred
+                                        case
+                                            when not_cleared then 1 / 0.1
+                                            when cw.winter_service = 'yes' then 1
+                                            when cw.winter_service = 'no' then 1 / 0.1
+                                            when cw.seasonal in ('yes', 'summer') then 1 / 0.1
+                                            when awe.tags->>'highway' = 'cycleway' then 1 / 0.5
+                                            else 1
+                                        end as winter_factor,
+                                        -- a recent hazard point within about 15 m, in 3857 units
+                                        exists (select 1 from hazard h
+                                            where not h.hidden
+                                            and h.created_at > now() - interval '$SCORE_HALF_LIFE_DAYS days'
+                                            and st_dwithin(h.geom, (segment).geom, 20)) as hazard,
+                                        -- bikes can't go against the way, like base_reverse_cost
+                                        coalesce(awe.tags->>'oneway:bicycle', awe.tags->>'oneway') = 'yes' as oneway,
                                         cost_road,
+                                        st_length(st_transform((segment).geom, 4326)::geography) as length_m,
+                                        (select st_value(d.rast, st_transform(ST_PointN((segment).geom, 1), st_srid(d.rast)))
+                                            from dem d
+                                            where st_intersects(d.rast, st_transform(ST_PointN((segment).geom, 1), st_srid(d.rast)))
+                                            limit 1) as z1,
+                                        (select st_value(d.rast, st_transform(ST_PointN((segment).geom, 2), st_srid(d.rast)))
+                                            from dem d
+                                            where st_intersects(d.rast, st_transform(ST_PointN((segment).geom, 2), st_srid(d.rast)))
+                                            limit 1) as z2,
                                         st_length((segment).geom) *
                                         CASE
                                             WHEN score IS NULL THEN 
                                                 cost_road
-                                            WHEN score = 0 THEN 1 / 0.001
-                                            ELSE cost_road * (1 / score)
-                                        END as cost,
+                                            -- the closures limited in time are applied when routing
+                                            WHEN score = 0 and (closed_from is not null or closed_until is not null) THEN cost_road
+                                            -- an old closure slowly opens again
+                                            WHEN score = 0 THEN least(1 / 0.001, cost_road / greatest(decayed_score, 0.001))
+                                            ELSE cost_road * (1 / decayed_score)
+                                        END as base_cost,
                                         st_length((segment).geom) *
                                         CASE
-                                            when awe.tags->>'oneway:bicycle' = 'no' and score is not null and score != 0 then cost_road * (1 / score)
+                                            when awe.tags->>'oneway:bicycle' = 'no' and score is not null and score != 0 then cost_road * (1 / decayed_score)
                                             when awe.tags->>'oneway:bicycle' = 'no' then cost_road
-                                            when awe.tags->>'oneway' = 'no' and score is not null and score != 0 then cost_road * (1 / score)
+                                            when awe.tags->>'oneway' = 'no' and score is not null and score != 0 then cost_road * (1 / decayed_score)
                                             when awe.tags->>'oneway:bicycle' = 'yes' then 1 / 0.001
                                             when awe.tags->>'oneway' = 'yes' then 1 / 0.001
                                             WHEN score IS NULL THEN
                                                 cost_road
-                                            WHEN score = 0 THEN 1 / 0.001
-                                            ELSE cost_road * (1 / score)
-                                        END as reverse_cost
+                                            WHEN score = 0 and (closed_from is not null or closed_until is not null) THEN cost_road
+                                            WHEN score = 0 THEN least(1 / 0.001, cost_road / greatest(decayed_score, 0.001))
+                                            ELSE cost_road * (1 / decayed_score)
+                                        END as base_reverse_cost
                                     from _all_way_edge awe
                                     left join  last_cycleway_score cs on cs.way_id = awe.way_id
-                                    where awe.nodes[(segment).path[1]+1] is not null;       
+                                    left join lateral (
+                                        select winter_service, seasonal
+                                        from cycleway_way
+                                        where cycleway_way.way_id = awe.way_id
+                                        limit 1
+                                    ) cw on true
+                                    where awe.nodes[(segment).path[1]+1] is not null) e;       
 
                                     CREATE INDEX edge_way_id_idx ON edge(way_id);
                                     CREATE INDEX edge_geom_idx ON edge using gist(geom);
@@ -0,0 +1,40 @@
+create table routing_profile (
+    id serial primary key,
+    name text not null unique,
+    label text not null,
+    -- exponent applied to 1 / score on top of the score already in the edge cost
+    score_penalty float8 not null default 0,
+    -- multiplier of the forward cost when riding against a oneway, null keeps the
+    -- reverse cost of the edge: 1000 times its length, avoided but not forbidden
+    contraflow_factor float8 null
+);
+
+create table routing_profile_weight (
+    profile_id integer not null references routing_profile(id) on delete cascade,
+    tag text not null,
+    value text not null,
+    weight float8 not null,
+    primary key (profile_id, tag, value)
+);
+
+insert into routing_profile (name, label, score_penalty, contraflow_factor) values
+    ('commuter', 'Navetteur', 0, null),
+    ('family', 'Famille', 1, null),
+    ('sport', 'Sportif', 0, 3);
+
+insert into routing_profile_weight (profile_id, tag, value, weight)
+select p.id, w.tag, w.value, w.weight
+from routing_profile p
+join (values
+    ('family', 'highway', 'cycleway', 0.8),
+    ('family', 'cycleway', 'track', 0.9),
+    ('family', 'highway', 'tertiary', 2),
+    ('family', 'highway', 'secondary', 3),
+    ('family', 'highway', 'primary', 5),
+    ('family', 'highway', 'trunk', 5),
+    ('sport', 'highway', 'footway', 3),
+    ('sport', 'highway', 'steps', 5),
+    ('sport', 'highway', 'cycleway', 1.2),
+    ('sport', 'highway', 'tertiary', 0.8),
+    ('sport', 'highway', 'secondary', 0.7)
+) as w(profile, tag, value, weight) on w.profile = p.name;
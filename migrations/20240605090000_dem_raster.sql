@@ -0,0 +1,10 @@
+-- Digital elevation model used to compute the grade of the edges.
+-- Load a GeoTIFF with: raster2pgsql -a -s <srid> -t 100x100 dem.tif dem | psql
+create extension if not exists postgis_raster;
+
+create table if not exists dem (
+    rid serial primary key,
+    rast raster not null
+);
+
+create index if not exists dem_rast_gist on dem using gist(st_convexhull(rast));
@@ -0,0 +1,11 @@
+create table shared_route (
+    id text primary key,
+    -- "lng,lat;lng,lat;..." like the /route/via urls
+    waypoints text not null,
+    kind text not null,
+    profile text not null,
+    -- the nodes of the route when it was shared, to know if it changed since
+    node_ids bigint[] not null,
+    geom geometry(LineString, 3857) not null,
+    created_at timestamptz not null default now()
+);
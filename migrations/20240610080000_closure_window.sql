@@ -0,0 +1,3 @@
+-- a closure (score 0) can be limited in time, like for construction work
+alter table cyclability_score add column closed_from timestamptz null;
+alter table cyclability_score add column closed_until timestamptz null;
@@ -0,0 +1,2 @@
+-- the way was reported as not cleared of snow
+alter table cyclability_score add column not_cleared boolean not null default false;
@@ -0,0 +1,2 @@
+-- a contribution hidden by a moderator is kept but ignored everywhere
+alter table cyclability_score add column hidden boolean not null default false;
@@ -0,0 +1,13 @@
+create table score_report (
+    id serial primary key,
+    cyclability_score_id integer not null references cyclability_score(id) on delete cascade,
+    -- spam, offensive, wrong or other
+    reason text not null,
+    -- the keycloak account of the reporter, anyone can get a new uuid cookie
+    -- and report again
+    reporter text not null,
+    -- a moderator looked at the contribution since
+    reviewed boolean not null default false,
+    created_at timestamptz not null default now(),
+    unique (cyclability_score_id, reporter)
+);
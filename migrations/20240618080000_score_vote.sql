@@ -0,0 +1,12 @@
+-- the last time a rider confirmed that the score is still accurate
+alter table cyclability_score add column confirmed_at timestamptz;
+
+create table score_vote (
+    cyclability_score_id integer not null references cyclability_score(id) on delete cascade,
+    -- the keycloak account of the rider, one vote each since the votes age the
+    -- scores in the routing costs
+    voter text not null,
+    still_valid boolean not null,
+    created_at timestamptz not null default now(),
+    primary key (cyclability_score_id, voter)
+);
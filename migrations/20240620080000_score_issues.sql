@@ -0,0 +1,3 @@
+-- the problems seen on the way: pothole, snow, parked_cars, construction,
+-- broken_glass, missing_signage, dangerous_intersection
+alter table cyclability_score add column issues text[] not null default '{}';
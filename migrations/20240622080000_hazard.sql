@@ -0,0 +1,16 @@
+-- a problem at a point rather than along ways, served as tiles by martin
+create table hazard (
+    id serial primary key,
+    geom geometry(Point, 3857) not null,
+    -- a value of `Issue`
+    issue text not null,
+    comment text,
+    user_id uuid,
+    hidden boolean not null default false,
+    created_at timestamptz not null default now()
+);
+create index hazard_geom_idx on hazard using gist(geom);
+
+-- multiplier of the cost of the edges near a hazard, null ignores the hazards
+alter table routing_profile add column hazard_factor float8 null;
+update routing_profile set hazard_factor = 2 where name = 'family';
@@ -0,0 +1,15 @@
+-- an area or a list of ways followed by a rider, sent as a daily digest
+create table subscription (
+    id serial primary key,
+    email text not null,
+    way_ids bigint[],
+    geom geometry(Polygon, 3857),
+    user_id uuid,
+    -- the secret of the unsubscribe link
+    token uuid not null unique default gen_random_uuid(),
+    -- pending until the link sent by email is followed
+    confirmed_at timestamptz,
+    last_sent_at timestamptz not null default now(),
+    created_at timestamptz not null default now(),
+    check (way_ids is not null or geom is not null)
+);
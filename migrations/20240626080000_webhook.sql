@@ -0,0 +1,26 @@
+-- the endpoints of the partners notified of the new contributions
+create table webhook (
+    id serial primary key,
+    url text not null,
+    -- the key of the HMAC-SHA256 signature of the payloads
+    secret text not null,
+    -- the values of `Category`, null is every category
+    categories text[],
+    -- null is everywhere
+    geom geometry(Polygon, 3857),
+    created_at timestamptz not null default now()
+);
+
+-- a payload to post to a webhook, retried with a backoff until delivered
+create table webhook_delivery (
+    id serial primary key,
+    webhook_id integer not null references webhook(id) on delete cascade,
+    cyclability_score_id integer not null references cyclability_score(id) on delete cascade,
+    payload jsonb not null,
+    attempts integer not null default 0,
+    next_attempt_at timestamptz not null default now(),
+    delivered_at timestamptz,
+    last_error text,
+    created_at timestamptz not null default now()
+);
+create index webhook_delivery_pending_idx on webhook_delivery(next_attempt_at) where delivered_at is null;
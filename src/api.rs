@@ -0,0 +1,475 @@
+// The JSON API used by the mobile client. It exposes the same data as the
+// panels, the schemas are the serialized types of the db module.
+//
+// The OpenAPI document is generated from the handlers below and served at
+// /api/openapi.json. The routes are registered from the paths of the document
+// so they can't disagree.
+//
+// The way ids are separated by any non digit character and the waypoints are
+// "lng,lat;lng,lat;..." like the /route/via urls. The errors are a status code
+// with a text body.
+use crate::db::cyclability_score::CyclabilityScore;
+use crate::db::cycleway::Cycleway;
+use crate::db::edge::{Point, RouteKind};
+use crate::db::routing_profile::RoutingProfile;
+use crate::db::search_db::{get, get_with_adress, SearchResultDB};
+use crate::instruction::Instruction;
+use crate::node::{
+    get_profile, parse_waypoints, route_alternatives, route_geojson_value, route_instructions,
+    RouteOptions,
+};
+use crate::VeloinfoState;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::get as get_route;
+use axum::{Json, Router};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+lazy_static! {
+    static ref WAY_ID_RE: Regex = Regex::new(r"\d+").unwrap();
+    static ref ADDRESS_RE: Regex = Regex::new(r"(\d+) (.*)").unwrap();
+    static ref PARAM_RE: Regex = Regex::new(r"\{(\w+)\}").unwrap();
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Vélo info",
+        description = "Cyclability of the bike paths and bike routing"
+    ),
+    paths(
+        cycleways,
+        history,
+        photos,
+        score,
+        recent_scores,
+        search,
+        profiles,
+        route
+    ),
+    components(schemas(
+        Cycleway,
+        CyclabilityScore,
+        SearchResultDB,
+        RoutingProfile,
+        RouteKind,
+        Point,
+        Instruction,
+        crate::instruction::Maneuver,
+        Photo,
+        ApiRoute,
+        ApiRouteAlternative
+    ))
+)]
+pub struct ApiDoc;
+
+pub fn v1() -> Router<VeloinfoState> {
+    Router::new()
+        .route(&route_path::<__path_cycleways>(), get_route(cycleways))
+        .route(&route_path::<__path_history>(), get_route(history))
+        .route(&route_path::<__path_photos>(), get_route(photos))
+        .route(&route_path::<__path_score>(), get_route(score))
+        .route(
+            &route_path::<__path_recent_scores>(),
+            get_route(recent_scores),
+        )
+        .route(&route_path::<__path_search>(), get_route(search))
+        .route(&route_path::<__path_profiles>(), get_route(profiles))
+        .route(&route_path::<__path_route>(), get_route(route))
+}
+
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// "/api/v1/cycleways/{way_ids}" becomes "/cycleways/:way_ids" for the router
+fn route_path<P: utoipa::Path>() -> String {
+    PARAM_RE
+        .replace_all(P::path().trim_start_matches("/api/v1"), ":$1")
+        .to_string()
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Photo {
+    pub id: i32,
+    pub url: String,
+    pub thumbnail_url: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct SearchQuery {
+    pub query: String,
+    /// The results are sorted by distance from this point
+    pub lng: f64,
+    pub lat: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiRoute {
+    /// Name of the routing profile
+    pub profile: String,
+    /// The kind asked first, then the other kinds giving a different path
+    pub alternatives: Vec<ApiRouteAlternative>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiRouteAlternative {
+    pub kind: RouteKind,
+    /// Meters
+    pub length: f64,
+    /// Minutes
+    pub duration: f64,
+    /// Percentage of the distance on dedicated cycleways
+    pub cycleway_share: f64,
+    pub average_score: Option<f64>,
+    /// Meters, 0 without elevation data
+    pub climb: f64,
+    pub descent: f64,
+    /// Meters of each leg between the waypoints
+    pub legs: Vec<f64>,
+    pub instructions: Vec<Instruction>,
+    /// One point at the start of each edge
+    pub points: Vec<Point>,
+    /// GeoJSON FeatureCollection of the segments like the /geojson export
+    #[schema(value_type = Object)]
+    pub geometry: Value,
+}
+
+fn way_ids(way_ids: &str) -> Vec<i64> {
+    WAY_ID_RE
+        .find_iter(way_ids)
+        .filter_map(|m| m.as_str().parse().ok())
+        .collect()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/cycleways/{way_ids}",
+    params(("way_ids" = String, Path, description = "Way ids separated by any non digit")),
+    responses(
+        (status = 200, body = [Cycleway]),
+        (status = 404, body = String, description = "A way is not a cycleway")
+    )
+)]
+async fn cycleways(
+    State(state): State<VeloinfoState>,
+    Path(ids): Path<String>,
+) -> Result<Json<Vec<Cycleway>>, (StatusCode, String)> {
+    let mut cycleways = vec![];
+    for way_id in way_ids(&ids) {
+        match Cycleway::get(&way_id, &state.conn).await {
+            Ok(cycleway) => cycleways.push(cycleway),
+            Err(e) => return Err((StatusCode::NOT_FOUND, format!("Way {}: {}", way_id, e))),
+        }
+    }
+    Ok(Json(cycleways))
+}
+
+// The newest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/cycleways/{way_ids}/history",
+    params(("way_ids" = String, Path, description = "Way ids separated by any non digit")),
+    responses((status = 200, body = [CyclabilityScore]))
+)]
+async fn history(
+    State(state): State<VeloinfoState>,
+    Path(ids): Path<String>,
+) -> Json<Vec<CyclabilityScore>> {
+    Json(CyclabilityScore::get_history(&way_ids(&ids), &state.conn).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/cycleways/{way_ids}/photos",
+    params(("way_ids" = String, Path, description = "Way ids separated by any non digit")),
+    responses((status = 200, body = [Photo]))
+)]
+async fn photos(State(state): State<VeloinfoState>, Path(ids): Path<String>) -> Json<Vec<Photo>> {
+    let photos = CyclabilityScore::get_photo_by_way_ids(&way_ids(&ids), &state.conn)
+        .await
+        .into_iter()
+        .map(|id| Photo {
+            id,
+            url: format!("/images/{id}.jpeg"),
+            thumbnail_url: format!("/images/{id}_thumbnail.jpeg"),
+        })
+        .collect();
+    Json(photos)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/scores/{id}",
+    params(("id" = i32, Path, description = "Id of the score")),
+    responses(
+        (status = 200, body = CyclabilityScore),
+        (status = 404, body = String)
+    )
+)]
+async fn score(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+) -> Result<Json<CyclabilityScore>, (StatusCode, String)> {
+    match CyclabilityScore::get_by_id(id, &state.conn).await {
+        Ok(score) if !score.hidden => Ok(Json(score)),
+        Ok(_) => Err((StatusCode::NOT_FOUND, format!("Score {}: hidden", id))),
+        Err(e) => Err((StatusCode::NOT_FOUND, format!("Score {}: {}", id, e))),
+    }
+}
+
+// The last 100 scores in the bounds
+#[utoipa::path(
+    get,
+    path = "/api/v1/scores/bounds/{lng1}/{lat1}/{lng2}/{lat2}",
+    params(
+        ("lng1" = f64, Path, description = "West"),
+        ("lat1" = f64, Path, description = "South"),
+        ("lng2" = f64, Path, description = "East"),
+        ("lat2" = f64, Path, description = "North")
+    ),
+    responses(
+        (status = 200, body = [CyclabilityScore]),
+        (status = 500, body = String)
+    )
+)]
+async fn recent_scores(
+    State(state): State<VeloinfoState>,
+    Path((lng1, lat1, lng2, lat2)): Path<(f64, f64, f64, f64)>,
+) -> Result<Json<Vec<CyclabilityScore>>, (StatusCode, String)> {
+    CyclabilityScore::get_recents(lng1, lat1, lng2, lat2, None, &state.conn)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// Same as the search box, "123 rue" looks for a civic number
+#[utoipa::path(
+    get,
+    path = "/api/v1/search",
+    params(SearchQuery),
+    // SearchResultDB is named SearchResult in the spec
+    responses((status = 200, body = [SearchResult]))
+)]
+async fn search(
+    State(state): State<VeloinfoState>,
+    Query(query): Query<SearchQuery>,
+) -> Json<Vec<SearchResultDB>> {
+    let results = match ADDRESS_RE.captures(&query.query) {
+        Some(caps) => match caps[1].parse::<i64>() {
+            Ok(number) => {
+                get_with_adress(
+                    &number,
+                    &caps[2].to_string(),
+                    &query.lng,
+                    &query.lat,
+                    &state.conn,
+                )
+                .await
+            }
+            Err(_) => get(&query.query, &query.lng, &query.lat, &state.conn).await,
+        },
+        None => get(&query.query, &query.lng, &query.lat, &state.conn).await,
+    };
+    Json(results)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/profiles",
+    responses((status = 200, body = [RoutingProfile]))
+)]
+async fn profiles(State(state): State<VeloinfoState>) -> Json<Vec<RoutingProfile>> {
+    Json(RoutingProfile::get_all(&state.conn).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/route/{waypoints}",
+    params(
+        ("waypoints" = String, Path, description = "lng,lat;lng,lat;... in the order they are visited"),
+        ("kind" = Option<RouteKind>, Query, description = "The kind listed first"),
+        ("profile" = Option<String>, Query, description = "commuter when missing"),
+        ("departure" = Option<String>, Query, description = "Local time like 2024-06-10T08:30, now when missing")
+    ),
+    responses(
+        (status = 200, body = ApiRoute),
+        (status = 400, body = String, description = "Malformed waypoints"),
+        (status = 404, body = String, description = "No route between the waypoints"),
+        (status = 500, body = String)
+    )
+)]
+async fn route(
+    State(state): State<VeloinfoState>,
+    Path(waypoints): Path<String>,
+    Query(options): Query<RouteOptions>,
+) -> Result<Json<ApiRoute>, (StatusCode, String)> {
+    let waypoints = parse_waypoints(&waypoints).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let profile = get_profile(&state, &options)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let routes = route_alternatives(&state, &waypoints, &options, &profile)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e))?;
+    let mut alternatives = vec![];
+    for (kind, route) in routes.iter() {
+        alternatives.push(ApiRouteAlternative {
+            kind: *kind,
+            length: route.length(),
+            duration: route.duration(),
+            cycleway_share: route.cycleway_share(),
+            average_score: route.average_score(),
+            climb: route.climb(),
+            descent: route.descent(),
+            legs: route.legs.clone(),
+            instructions: route_instructions(&state, route).await,
+            points: route.points.clone(),
+            geometry: route_geojson_value(&route.points),
+        });
+    }
+    Ok(Json(ApiRoute {
+        profile: profile.name,
+        alternatives,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+
+    // The output of a handler, whatever the number of extractors
+    trait Handler<Args> {
+        type Output;
+    }
+
+    impl<F, Fut, A> Handler<(A,)> for F
+    where
+        F: Fn(A) -> Fut,
+        Fut: Future,
+    {
+        type Output = Fut::Output;
+    }
+
+    impl<F, Fut, A, B> Handler<(A, B)> for F
+    where
+        F: Fn(A, B) -> Fut,
+        Fut: Future,
+    {
+        type Output = Fut::Output;
+    }
+
+    impl<F, Fut, A, B, C> Handler<(A, B, C)> for F
+    where
+        F: Fn(A, B, C) -> Fut,
+        Fut: Future,
+    {
+        type Output = Fut::Output;
+    }
+
+    // The body of the 200 response
+    trait Body {
+        type Body;
+    }
+
+    impl<T> Body for Json<T> {
+        type Body = T;
+    }
+
+    impl<T, E> Body for Result<Json<T>, E> {
+        type Body = T;
+    }
+
+    // Written like in the spec, "Cycleway" or "[Cycleway]" for an array
+    trait SchemaName {
+        fn schema_name() -> String;
+    }
+
+    impl<T: ToSchema<'static>> SchemaName for Vec<T> {
+        fn schema_name() -> String {
+            format!("[{}]", T::schema().0)
+        }
+    }
+
+    impl SchemaName for CyclabilityScore {
+        fn schema_name() -> String {
+            CyclabilityScore::schema().0.to_string()
+        }
+    }
+
+    impl SchemaName for ApiRoute {
+        fn schema_name() -> String {
+            ApiRoute::schema().0.to_string()
+        }
+    }
+
+    fn body<H, Args>(_handler: H) -> String
+    where
+        H: Handler<Args>,
+        H::Output: Body,
+        <H::Output as Body>::Body: SchemaName,
+    {
+        <H::Output as Body>::Body::schema_name()
+    }
+
+    fn spec_body(doc: &Value, path: &str) -> String {
+        let schema =
+            &doc["paths"][path]["get"]["responses"]["200"]["content"]["application/json"]["schema"];
+        let name = |schema: &Value| {
+            schema["$ref"]
+                .as_str()
+                .and_then(|reference| reference.rsplit('/').next())
+                .unwrap_or("")
+                .to_string()
+        };
+        match schema["type"].as_str() {
+            Some("array") => format!("[{}]", name(&schema["items"])),
+            _ => name(schema),
+        }
+    }
+
+    #[test]
+    fn handlers_match_the_spec() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let handlers = [
+            ("/api/v1/cycleways/{way_ids}", body(cycleways)),
+            ("/api/v1/cycleways/{way_ids}/history", body(history)),
+            ("/api/v1/cycleways/{way_ids}/photos", body(photos)),
+            ("/api/v1/scores/{id}", body(score)),
+            (
+                "/api/v1/scores/bounds/{lng1}/{lat1}/{lng2}/{lat2}",
+                body(recent_scores),
+            ),
+            ("/api/v1/search", body(search)),
+            ("/api/v1/profiles", body(profiles)),
+            ("/api/v1/route/{waypoints}", body(route)),
+        ];
+        assert_eq!(
+            doc["paths"].as_object().unwrap().len(),
+            handlers.len(),
+            "every path of the spec must be checked here"
+        );
+        for (path, body) in handlers {
+            assert_eq!(spec_body(&doc, path), body, "response of {}", path);
+            let name = body.trim_matches(|c| c == '[' || c == ']');
+            assert!(
+                doc["components"]["schemas"].get(name).is_some(),
+                "{} is missing from the components",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn route_paths() {
+        assert_eq!(route_path::<__path_cycleways>(), "/cycleways/:way_ids");
+        assert_eq!(
+            route_path::<__path_recent_scores>(),
+            "/scores/bounds/:lng1/:lat1/:lng2/:lat2"
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use axum::{extract::Query, response::Redirect};
-use axum_extra::extract::cookie::{Cookie, CookieJar};
+use axum_extra::extract::cookie::{Cookie, Key, SignedCookieJar};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
@@ -8,8 +8,18 @@ lazy_static! {
         std::env::var("KEYCLOAK_SERVER_URL").expect("KEYCLOAK_SERVER_URL must be set");
     static ref VELOINFO_URL: String =
         std::env::var("VELOINFO_URL").expect("VELOINFO_URL must be set");
+    // signs the userinfo cookie so the roles can be trusted
+    pub static ref COOKIE_KEY: Key = Key::try_from(
+        std::env::var("COOKIE_KEY")
+            .expect("COOKIE_KEY must be set")
+            .as_bytes()
+    )
+    .expect("COOKIE_KEY must be at least 64 bytes long");
 }
 
+// The Keycloak realm role of the moderators
+const ADMIN_ROLE: &str = "admin";
+
 #[derive(Debug, Deserialize)]
 pub struct Auth {
     code: String,
@@ -24,7 +34,7 @@ struct Token {
     token_type: String,
 }
 
-pub async fn auth(auth: Query<Auth>, jar: CookieJar) -> (CookieJar, Redirect) {
+pub async fn auth(auth: Query<Auth>, jar: SignedCookieJar) -> (SignedCookieJar, Redirect) {
     let code = auth.code.clone();
 
     let client = reqwest::Client::new();
@@ -100,17 +110,49 @@ pub async fn auth(auth: Query<Auth>, jar: CookieJar) -> (CookieJar, Redirect) {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
-struct Userinfo {
-    sub: String,
-    email: String,
+pub struct Userinfo {
+    pub sub: String,
+    pub email: String,
     email_verified: bool,
     name: String,
     preferred_username: String,
     given_name: String,
     family_name: String,
+    // needs the realm roles mapper to be added to the userinfo in Keycloak
+    #[serde(default)]
+    realm_access: RealmAccess,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+struct RealmAccess {
+    roles: Vec<String>,
+}
+
+impl Userinfo {
+    pub fn get(jar: &SignedCookieJar) -> Option<Userinfo> {
+        let cookie = jar.get("userinfo")?;
+        match serde_json::from_str(cookie.value()) {
+            Ok(userinfo) => Some(userinfo),
+            Err(e) => {
+                eprintln!("Error parsing userinfo: {:?}", e);
+                None
+            }
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.realm_access
+            .roles
+            .iter()
+            .any(|role| role == ADMIN_ROLE)
+    }
+}
+
+pub fn is_admin(jar: &SignedCookieJar) -> bool {
+    Userinfo::get(jar).is_some_and(|userinfo| userinfo.is_admin())
 }
 
-pub async fn logout(jar: CookieJar) -> (CookieJar, Redirect) {
+pub async fn logout(jar: SignedCookieJar) -> (SignedCookieJar, Redirect) {
     println!("Logout");
 
     (jar.remove(Cookie::build("userinfo")), Redirect::to("/"))
@@ -0,0 +1,189 @@
+use crate::component::score_selector::{issue_labels, Category};
+use crate::db::cyclability_score::CyclabilityScore;
+use crate::VeloinfoState;
+use askama::Template;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use chrono::Local;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::env;
+
+lazy_static! {
+    static ref VELOINFO_URL: String = env::var("VELOINFO_URL").expect("VELOINFO_URL must be set");
+}
+
+#[derive(Template)]
+#[template(path = "feed.xml")]
+pub struct Feed {
+    title: String,
+    self_url: String,
+    url: String,
+    updated: String,
+    entries: Vec<FeedEntry>,
+}
+
+pub struct FeedEntry {
+    url: String,
+    title: String,
+    updated: String,
+    // the absolute url of the photo thumbnail
+    thumbnail: Option<String>,
+    // escaped html, it is escaped again in the xml
+    content: String,
+}
+
+#[derive(Template)]
+#[template(path = "feed_entry.html")]
+struct FeedEntryContent<'a> {
+    issues: Vec<&'static str>,
+    comment: &'a str,
+    thumbnail: &'a Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FeedQuery {
+    // lng1,lat1,lng2,lat2
+    bbox: Option<String>,
+    // the way_ids of a segment, separated by commas or spaces
+    way_ids: Option<String>,
+}
+
+// /feed.atom?bbox=lng1,lat1,lng2,lat2 or /feed.atom?way_ids=1,2
+pub async fn feed(
+    State(state): State<VeloinfoState>,
+    Query(query): Query<FeedQuery>,
+) -> Result<(HeaderMap, Feed), (StatusCode, String)> {
+    let (title, self_url, scores) = match (query.bbox, query.way_ids) {
+        (Some(bbox), _) => {
+            let bbox = bbox
+                .split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .ok()
+                .filter(|bbox| bbox.len() == 4)
+                .ok_or((
+                    StatusCode::BAD_REQUEST,
+                    "bbox must be lng1,lat1,lng2,lat2".to_string(),
+                ))?;
+            let scores = CyclabilityScore::get_recents(
+                bbox[0],
+                bbox[1],
+                bbox[2],
+                bbox[3],
+                None,
+                &state.conn,
+            )
+            .await
+            .map_err(|e| {
+                eprintln!("Error while getting the scores of the feed: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            })?;
+            let self_url = format!(
+                "{}/feed.atom?bbox={}",
+                VELOINFO_URL.as_str(),
+                bbox.iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            );
+            (
+                "Contributions dans cette zone".to_string(),
+                self_url,
+                scores,
+            )
+        }
+        (None, Some(way_ids)) => {
+            let way_ids = way_ids
+                .split(|c: char| !c.is_ascii_digit())
+                .filter_map(|way_id| way_id.parse::<i64>().ok())
+                .collect::<Vec<i64>>();
+            if way_ids.is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "no way_ids".to_string()));
+            }
+            let scores = CyclabilityScore::get_history(&way_ids, &state.conn).await;
+            let title = scores
+                .first()
+                .map(score_name)
+                .unwrap_or("Contributions".to_string());
+            let self_url = format!(
+                "{}/feed.atom?way_ids={}",
+                VELOINFO_URL.as_str(),
+                way_ids
+                    .iter()
+                    .map(|way_id| way_id.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            );
+            (title, self_url, scores)
+        }
+        (None, None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "bbox or way_ids is needed".to_string(),
+            ))
+        }
+    };
+
+    let updated = scores
+        .first()
+        .map(|score| score.created_at)
+        .unwrap_or(Local::now());
+    let entries = scores
+        .iter()
+        .map(|score| {
+            let thumbnail = score.photo_path_thumbnail.as_ref().map(|_| {
+                format!(
+                    "{}/images/{}_thumbnail.jpeg",
+                    VELOINFO_URL.as_str(),
+                    score.id
+                )
+            });
+            let content = FeedEntryContent {
+                issues: issue_labels(&score.issues),
+                comment: score.comment.as_deref().unwrap_or_default(),
+                thumbnail: &thumbnail,
+            }
+            .render()
+            .unwrap_or_default();
+            FeedEntry {
+                url: format!("{}/?score={}", VELOINFO_URL.as_str(), score.id),
+                title: format!(
+                    "{} : {}",
+                    score_name(score),
+                    Category::from_score(score.score).label()
+                ),
+                updated: score.created_at.to_rfc3339(),
+                thumbnail,
+                content,
+            }
+        })
+        .collect();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "Content-Type",
+        HeaderValue::from_static("application/atom+xml; charset=utf-8"),
+    );
+    Ok((
+        headers,
+        Feed {
+            title,
+            self_url,
+            url: VELOINFO_URL.to_string(),
+            updated: updated.to_rfc3339(),
+            entries,
+        },
+    ))
+}
+
+fn score_name(score: &CyclabilityScore) -> String {
+    score
+        .name
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>()
+        .join(", ")
+}
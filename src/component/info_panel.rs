@@ -1,9 +1,12 @@
 use super::score_circle::ScoreCircle;
+use super::score_selector::{issue_labels, Issue};
+use super::score_votes::ScoreVotes;
 use crate::db::cyclability_score::CyclabilityScore;
+use crate::db::hazard::Hazard;
 use crate::db::user::User;
 use crate::VeloinfoState;
 use askama::Template;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use chrono::Locale;
 use chrono_tz::America::Montreal;
 use futures::future::join_all;
@@ -18,6 +21,9 @@ use timeago::languages::french::French;
 pub struct InfoPanelTemplate {
     pub arrow: String,
     pub contributions: Vec<InfopanelContribution>,
+    pub hazards: Vec<InfopanelHazard>,
+    // value, label, selected
+    pub issue_options: Vec<(&'static str, &'static str, bool)>,
 }
 
 #[derive(Template, Clone)]
@@ -31,6 +37,52 @@ pub struct InfopanelContribution {
     score_id: i32,
     photo_path_thumbnail: Option<String>,
     user_name: String,
+    issues: Vec<&'static str>,
+    votes: ScoreVotes,
+}
+
+// The hazards are posted without login, their comments are escaped here
+#[derive(Template, Clone)]
+#[template(path = "info_panel_hazard.html")]
+pub struct InfopanelHazard {
+    timeago: String,
+    label: String,
+    comment: String,
+    lng: f64,
+    lat: f64,
+}
+
+impl InfopanelHazard {
+    pub async fn get(
+        lng1: f64,
+        lat1: f64,
+        lng2: f64,
+        lat2: f64,
+        issue: Option<&str>,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Vec<InfopanelHazard> {
+        let hazards = match Hazard::get_recents(lng1, lat1, lng2, lat2, issue, conn).await {
+            Ok(hazards) => hazards,
+            Err(e) => {
+                eprintln!("Error getting hazards {:?}", e);
+                Vec::new()
+            }
+        };
+        hazards
+            .iter()
+            .map(|hazard| InfopanelHazard {
+                timeago: timeago::Formatter::with_language(French)
+                    .convert_chrono(hazard.created_at, Local::now()),
+                label: Issue::from_value(&hazard.issue)
+                    .map(|issue| issue.label())
+                    .unwrap_or_default()
+                    .to_string(),
+                comment: hazard.comment.clone().unwrap_or_default(),
+                lng: hazard.lng,
+                lat: hazard.lat,
+            })
+            .collect()
+    }
 }
 
 impl InfopanelContribution {
@@ -39,9 +91,11 @@ impl InfopanelContribution {
         lat1: f64,
         lng2: f64,
         lat2: f64,
+        issue: Option<&str>,
         conn: &sqlx::Pool<Postgres>,
     ) -> Vec<InfopanelContribution> {
-        let scores = match CyclabilityScore::get_recents(lng1, lat1, lng2, lat2, conn).await {
+        let scores = match CyclabilityScore::get_recents(lng1, lat1, lng2, lat2, issue, conn).await
+        {
             Result::Ok(cs) => cs,
             Err(e) => {
                 eprintln!("Error getting contributions {:?}", e);
@@ -49,6 +103,8 @@ impl InfopanelContribution {
             }
         };
 
+        let score_ids = scores.iter().map(|score| score.id).collect::<Vec<i32>>();
+        let votes = ScoreVotes::get_many(&score_ids, conn).await;
         join_all(scores.iter().map(|score| async {
             InfopanelContribution {
                 created_at: score
@@ -70,6 +126,8 @@ impl InfopanelContribution {
                     },
                     None => "".to_string(),
                 },
+                issues: issue_labels(&score.issues),
+                votes: votes[&score.id].clone(),
             }
         }))
         .await
@@ -81,6 +139,8 @@ impl InfopanelContribution {
     ) -> Vec<InfopanelContribution> {
         let scores = CyclabilityScore::get_history(way_ids, conn).await;
 
+        let score_ids = scores.iter().map(|score| score.id).collect::<Vec<i32>>();
+        let votes = ScoreVotes::get_many(&score_ids, conn).await;
         join_all(scores.iter().map(|score| async {
             InfopanelContribution {
                 created_at: score
@@ -102,6 +162,8 @@ impl InfopanelContribution {
                     },
                     None => "".to_string(),
                 },
+                issues: issue_labels(&score.issues),
+                votes: votes[&score.id].clone(),
             }
         }))
         .await
@@ -119,6 +181,8 @@ impl InfopanelContribution {
             }
         };
 
+        let score_ids = scores.iter().map(|score| score.id).collect::<Vec<i32>>();
+        let votes = ScoreVotes::get_many(&score_ids, conn).await;
         join_all(scores.iter().map(|score| async {
             InfopanelContribution {
                 created_at: score
@@ -140,6 +204,8 @@ impl InfopanelContribution {
                     },
                     None => "".to_string(),
                 },
+                issues: issue_labels(&score.issues),
+                votes: votes[&score.id].clone(),
             }
         }))
         .await
@@ -168,6 +234,8 @@ pub async fn info_panel_down() -> InfoPanelTemplate {
     InfoPanelTemplate {
         arrow: "▲".to_string(),
         contributions: Vec::new(),
+        hazards: Vec::new(),
+        issue_options: vec![],
     }
 }
 
@@ -177,13 +245,37 @@ pub struct LatLng {
     pub lng: f64,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct IssueFilter {
+    pub issue: Option<String>,
+}
+
 pub async fn info_panel_up(
     State(state): State<VeloinfoState>,
     Path((lng1, lat1, lng2, lat2)): Path<(f64, f64, f64, f64)>,
+    Query(filter): Query<IssueFilter>,
 ) -> InfoPanelTemplate {
-    let contributions = InfopanelContribution::get(lng1, lat1, lng2, lat2, &state.conn).await;
+    let issue = filter
+        .issue
+        .as_deref()
+        .and_then(Issue::from_value)
+        .map(|issue| issue.value());
+    let contributions =
+        InfopanelContribution::get(lng1, lat1, lng2, lat2, issue, &state.conn).await;
+    let hazards = InfopanelHazard::get(lng1, lat1, lng2, lat2, issue, &state.conn).await;
     InfoPanelTemplate {
         arrow: "▼".to_string(),
         contributions: contributions,
+        hazards,
+        issue_options: Issue::ALL
+            .iter()
+            .map(|option| {
+                (
+                    option.value(),
+                    option.label(),
+                    issue == Some(option.value()),
+                )
+            })
+            .collect(),
     }
 }
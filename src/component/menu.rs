@@ -1,5 +1,7 @@
+use crate::auth::is_admin;
 use askama::Template;
 use axum::extract::Path;
+use axum_extra::extract::SignedCookieJar;
 use lazy_static::lazy_static;
 use serde::Deserialize;
 use std::env;
@@ -17,6 +19,7 @@ pub struct Menu {
     lat: f64,
     lng: f64,
     zoom: i32,
+    admin: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,7 +29,7 @@ pub struct Position {
     zoom: f64,
 }
 
-pub async fn menu_open(Path(position): Path<Position>) -> Menu {
+pub async fn menu_open(Path(position): Path<Position>, jar: SignedCookieJar) -> Menu {
     let lat = position.lat;
     let lng = position.lng;
     let zoom = position.zoom.floor() as i32;
@@ -35,6 +38,7 @@ pub async fn menu_open(Path(position): Path<Position>) -> Menu {
         lat,
         lng,
         zoom,
+        admin: is_admin(&jar),
     }
 }
 
@@ -47,5 +51,6 @@ pub async fn menu_close() -> Menu {
         lat,
         lng,
         zoom,
+        admin: false,
     }
 }
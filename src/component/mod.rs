@@ -1,11 +1,18 @@
+pub mod feed;
 pub mod index_js;
 pub mod info_panel;
 pub mod menu;
+pub mod moderation;
 pub mod photo_scroll;
 pub mod point_panel;
+pub mod report;
+pub mod route_gpx;
 pub mod route_panel;
 pub mod score_circle;
 pub mod score_selector;
+pub mod score_votes;
 pub mod search;
 pub mod segment_panel;
 pub mod style;
+pub mod subscription;
+pub mod webhook_panel;
@@ -0,0 +1,205 @@
+use crate::auth::is_admin;
+use crate::component::report::reason_label;
+use crate::component::score_selector::Issue;
+use crate::db::cyclability_score::CyclabilityScore;
+use crate::db::hazard::Hazard;
+use crate::db::score_report::ScoreReport;
+use crate::db::user::User;
+use crate::VeloinfoState;
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum_extra::extract::SignedCookieJar;
+use chrono::Locale;
+use chrono_tz::America::Montreal;
+use futures::future::join_all;
+use sqlx::Postgres;
+
+// The contributions are written by anyone, they are escaped here
+#[derive(Template)]
+#[template(path = "moderation_panel.html")]
+pub struct ModerationPanel {
+    contributions: Vec<ModeratedContribution>,
+    hazards: Vec<ModeratedHazardLine>,
+}
+
+struct ModeratedHazardLine {
+    id: i32,
+    created_at: String,
+    label: String,
+    comment: String,
+    hidden: bool,
+}
+
+struct ModeratedContribution {
+    score_id: i32,
+    created_at: String,
+    name: String,
+    comment: String,
+    user_name: String,
+    has_photo: bool,
+    hidden: bool,
+    // the reasons of the reports not yet reviewed
+    reports: Vec<String>,
+}
+
+impl ModerationPanel {
+    async fn get(conn: &sqlx::Pool<Postgres>) -> ModerationPanel {
+        let scores = match CyclabilityScore::get_moderation(conn).await {
+            Ok(scores) => scores,
+            Err(e) => {
+                eprintln!("Error getting the contributions to moderate {:?}", e);
+                vec![]
+            }
+        };
+        let score_ids = scores.iter().map(|score| score.id).collect::<Vec<i32>>();
+        let reasons = ScoreReport::get_reasons(&score_ids, conn).await;
+        let contributions = join_all(scores.iter().map(|score| async {
+            ModeratedContribution {
+                score_id: score.id,
+                created_at: score
+                    .created_at
+                    .with_timezone(&Montreal)
+                    .format_localized("%H:%M - %d %B", Locale::fr_CA)
+                    .to_string(),
+                name: score
+                    .name
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                comment: score.comment.clone().unwrap_or_default(),
+                user_name: match score.user_id {
+                    Some(user_id) => match User::get(&user_id, conn).await {
+                        Some(user) => user.name,
+                        None => "".to_string(),
+                    },
+                    None => "".to_string(),
+                },
+                has_photo: score.photo_path_thumbnail.is_some(),
+                hidden: score.hidden,
+                reports: reasons
+                    .get(&score.id)
+                    .map(|reasons| {
+                        reasons
+                            .iter()
+                            .map(|reason| reason_label(reason).to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            }
+        }))
+        .await;
+        let hazards = match Hazard::get_moderation(conn).await {
+            Ok(hazards) => hazards,
+            Err(e) => {
+                eprintln!("Error getting the hazards to moderate {:?}", e);
+                vec![]
+            }
+        };
+        let hazards = hazards
+            .into_iter()
+            .map(|hazard| ModeratedHazardLine {
+                id: hazard.id,
+                created_at: hazard
+                    .created_at
+                    .with_timezone(&Montreal)
+                    .format_localized("%H:%M - %d %B", Locale::fr_CA)
+                    .to_string(),
+                label: Issue::from_value(&hazard.issue)
+                    .map(|issue| issue.label())
+                    .unwrap_or_default()
+                    .to_string(),
+                comment: hazard.comment.unwrap_or_default(),
+                hidden: hazard.hidden,
+            })
+            .collect();
+        ModerationPanel {
+            contributions,
+            hazards,
+        }
+    }
+}
+
+pub async fn moderation_panel(
+    State(state): State<VeloinfoState>,
+    jar: SignedCookieJar,
+) -> Result<ModerationPanel, StatusCode> {
+    if !is_admin(&jar) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(ModerationPanel::get(&state.conn).await)
+}
+
+pub async fn moderation_hide(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+    jar: SignedCookieJar,
+) -> Result<ModerationPanel, StatusCode> {
+    set_hidden(state, id, true, jar).await
+}
+
+pub async fn moderation_show(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+    jar: SignedCookieJar,
+) -> Result<ModerationPanel, StatusCode> {
+    set_hidden(state, id, false, jar).await
+}
+
+async fn set_hidden(
+    state: VeloinfoState,
+    id: i32,
+    hidden: bool,
+    jar: SignedCookieJar,
+) -> Result<ModerationPanel, StatusCode> {
+    if !is_admin(&jar) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Err(e) = CyclabilityScore::set_hidden(id, hidden, &state.conn).await {
+        eprintln!("Error while hiding the score {}: {}", id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    // shown again by a moderator, the old reports don't count anymore
+    if !hidden {
+        if let Err(e) = ScoreReport::review(id, &state.conn).await {
+            eprintln!("Error while reviewing the reports of {}: {}", id, e);
+        }
+    }
+    Ok(ModerationPanel::get(&state.conn).await)
+}
+
+pub async fn moderation_hazard_hide(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+    jar: SignedCookieJar,
+) -> Result<ModerationPanel, StatusCode> {
+    set_hazard_hidden(state, id, true, jar).await
+}
+
+pub async fn moderation_hazard_show(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+    jar: SignedCookieJar,
+) -> Result<ModerationPanel, StatusCode> {
+    set_hazard_hidden(state, id, false, jar).await
+}
+
+// the routes follow at the next refresh of the edge view
+async fn set_hazard_hidden(
+    state: VeloinfoState,
+    id: i32,
+    hidden: bool,
+    jar: SignedCookieJar,
+) -> Result<ModerationPanel, StatusCode> {
+    if !is_admin(&jar) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Err(e) = Hazard::set_hidden(id, hidden, &state.conn).await {
+        eprintln!("Error while hiding the hazard {}: {}", id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    Ok(ModerationPanel::get(&state.conn).await)
+}
@@ -1,20 +1,91 @@
+use super::score_selector::Issue;
+use super::segment_panel::cookie_user_id_or_new;
+use crate::db::hazard::Hazard;
 use crate::{db::search_db, VeloinfoState};
 use askama::Template;
 use axum::extract::{Path, State};
+use axum::Form;
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
 
 #[derive(Template)]
 #[template(path = "point_panel.html", escape = "none")]
 pub struct PointPanel {
     name: String,
+    lng: f64,
+    lat: f64,
+    // value, label
+    issue_options: Vec<(&'static str, &'static str)>,
+    message: String,
+}
+
+impl PointPanel {
+    async fn get(lng: f64, lat: f64, message: &str, conn: &sqlx::Pool<sqlx::Postgres>) -> Self {
+        let name = match search_db::get_any(&lng, &lat, conn).await.first() {
+            Some(ar) => ar.name.clone(),
+            None => "".to_string(),
+        };
+        PointPanel {
+            name,
+            lng,
+            lat,
+            issue_options: Issue::ALL
+                .iter()
+                .map(|issue| (issue.value(), issue.label()))
+                .collect(),
+            message: message.to_string(),
+        }
+    }
 }
 
 pub async fn point_panel_lng_lat(
     Path((lng, lat)): Path<(f64, f64)>,
     state: State<VeloinfoState>,
 ) -> PointPanel {
-    let name = match search_db::get_any(&lng, &lat, &state.conn).await.first() {
-        Some(ar) => ar.name.clone(),
-        None => "".to_string(),
+    PointPanel::get(lng, lat, "", &state.conn).await
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HazardForm {
+    lng: f64,
+    lat: f64,
+    issue: String,
+    comment: String,
+}
+
+pub async fn hazard_post(
+    State(state): State<VeloinfoState>,
+    jar: CookieJar,
+    Form(form): Form<HazardForm>,
+) -> (CookieJar, PointPanel) {
+    let (jar, user_id) = cookie_user_id_or_new(jar);
+    let message = match Issue::from_value(&form.issue) {
+        Some(issue) => {
+            let comment = Some(form.comment).filter(|comment| !comment.is_empty());
+            match Hazard::insert(
+                form.lng,
+                form.lat,
+                issue.value(),
+                &comment,
+                &user_id,
+                &state.conn,
+            )
+            .await
+            {
+                Ok(_) => "Merci, le danger est signalé.",
+                Err(e) => {
+                    eprintln!("Error while inserting hazard: {}", e);
+                    "Le danger n'a pas pu être signalé."
+                }
+            }
+        }
+        None => {
+            eprintln!("Unknown hazard issue: {}", form.issue);
+            "Le danger n'a pas pu être signalé."
+        }
     };
-    PointPanel { name }
+    (
+        jar,
+        PointPanel::get(form.lng, form.lat, message, &state.conn).await,
+    )
 }
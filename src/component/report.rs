@@ -0,0 +1,129 @@
+use crate::auth::Userinfo;
+use crate::db::cyclability_score::CyclabilityScore;
+use crate::db::score_report::ScoreReport;
+use crate::VeloinfoState;
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::Form;
+use axum_extra::extract::SignedCookieJar;
+use serde::Deserialize;
+
+// value, label
+pub const REASONS: [(&str, &str); 4] = [
+    ("spam", "Publicité"),
+    ("offensive", "Contenu offensant"),
+    ("wrong", "Information fausse"),
+    ("other", "Autre"),
+];
+
+pub fn reason_label(reason: &str) -> &str {
+    REASONS
+        .iter()
+        .find(|(value, _)| *value == reason)
+        .map(|(_, label)| *label)
+        .unwrap_or(reason)
+}
+
+#[derive(Template)]
+#[template(path = "report.html", escape = "none")]
+pub struct Report {
+    score_id: i32,
+    reasons: Vec<(&'static str, &'static str)>,
+    sent: bool,
+    // only the logged in riders report, a uuid cookie is free to mint
+    login_needed: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReportForm {
+    reason: String,
+}
+
+pub async fn report_form(Path(score_id): Path<i32>, jar: SignedCookieJar) -> Report {
+    Report {
+        score_id,
+        reasons: REASONS.to_vec(),
+        sent: false,
+        login_needed: Userinfo::get(&jar).is_none(),
+    }
+}
+
+pub async fn report_post(
+    State(state): State<VeloinfoState>,
+    Path(score_id): Path<i32>,
+    jar: SignedCookieJar,
+    Form(form): Form<ReportForm>,
+) -> Report {
+    let Some(userinfo) = Userinfo::get(&jar) else {
+        return Report {
+            score_id,
+            reasons: vec![],
+            sent: false,
+            login_needed: true,
+        };
+    };
+    let form_again = Report {
+        score_id,
+        reasons: REASONS.to_vec(),
+        sent: false,
+        login_needed: false,
+    };
+    if !REASONS.iter().any(|(value, _)| *value == form.reason) {
+        eprintln!("Unknown report reason: {}", form.reason);
+        return form_again;
+    }
+
+    // the reporter is counted once per contribution
+    match ScoreReport::insert(score_id, &form.reason, &userinfo.sub, &state.conn).await {
+        // hidden until a moderator reviews it
+        Ok(true) => {
+            if let Err(e) = CyclabilityScore::set_hidden(score_id, true, &state.conn).await {
+                eprintln!("Error while hiding the reported score {}: {}", score_id, e);
+            }
+        }
+        Ok(false) => (),
+        Err(e) => {
+            eprintln!("Error while reporting the score {}: {}", score_id, e);
+            return form_again;
+        }
+    }
+
+    Report {
+        score_id,
+        reasons: vec![],
+        sent: true,
+        login_needed: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::score_report::REPORT_THRESHOLD;
+    use axum_extra::extract::cookie::Key;
+    use sqlx::postgres::PgPoolOptions;
+
+    // Without a keycloak account a client can't report, whatever its uuid cookie,
+    // so sending the report again never reaches the threshold.
+    #[tokio::test]
+    async fn repeated_reports_without_login_dont_hide() {
+        // never connected, a report reaching the database would fail
+        let conn = PgPoolOptions::new()
+            .connect_lazy("postgres://nobody@localhost:1/none")
+            .unwrap();
+        let state = VeloinfoState { conn };
+        for _ in 0..REPORT_THRESHOLD {
+            let report = report_post(
+                State(state.clone()),
+                Path(1),
+                SignedCookieJar::new(Key::generate()),
+                Form(ReportForm {
+                    reason: "spam".to_string(),
+                }),
+            )
+            .await;
+            assert!(report.login_needed);
+            assert!(!report.sent);
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use crate::db::edge::Point;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "route_gpx.xml")]
+pub struct RouteGpx {
+    pub name: String,
+    pub points: Vec<Point>,
+}
@@ -1,3 +1,5 @@
+use crate::db::routing_profile::RoutingProfile;
+use crate::instruction::Instruction;
 use askama::Template;
 
 #[derive(Template)]
@@ -5,5 +7,87 @@ use askama::Template;
 pub struct RoutePanel {
     pub route_json: String,
     pub total_length: f64,
+    // estimated ride time in minutes
+    pub duration: f64,
+    pub legs: Vec<f64>,
+    pub alternatives: Vec<RouteAlternative>,
     pub error: String,
+    // shown above the route, like when a shared route changed since
+    pub warning: String,
+    pub export_path: String,
+    // name of the selected routing profile
+    pub profile: String,
+    pub profiles: Vec<RoutingProfile>,
+    // empty when leaving now
+    pub departure: String,
+}
+
+impl RoutePanel {
+    pub fn error(error: String) -> RoutePanel {
+        RoutePanel {
+            route_json: "[]".to_string(),
+            total_length: 0.0,
+            duration: 0.0,
+            legs: vec![],
+            alternatives: vec![],
+            error,
+            warning: "".to_string(),
+            export_path: "".to_string(),
+            profile: "".to_string(),
+            profiles: vec![],
+            departure: "".to_string(),
+        }
+    }
+}
+
+pub struct RouteAlternative {
+    pub name: String,
+    pub export_query: String,
+    pub route_json: String,
+    pub total_length: f64,
+    // percentage of the distance on dedicated cycleways
+    pub cycleway_share: f64,
+    pub average_score: Option<f64>,
+    pub instructions: Vec<Instruction>,
+    // estimated ride time in minutes
+    pub duration: f64,
+    // meters
+    pub climb: f64,
+    pub descent: f64,
+    // points of the svg polyline, empty without elevation data
+    pub elevation_profile: String,
+    pub categories: Vec<CategoryLength>,
+    // the dangerous and closed segments on the route
+    pub reported: Vec<ReportedSegment>,
+}
+
+pub struct CategoryLength {
+    pub label: String,
+    // meters
+    pub length: f64,
+}
+
+pub struct ReportedSegment {
+    pub score_id: i32,
+    pub name: String,
+    pub label: String,
+    // meters
+    pub length: f64,
+}
+
+// The link to a saved route
+#[derive(Template)]
+#[template(path = "route_share.html")]
+pub struct RouteShare {
+    pub path: String,
+    pub error: String,
+}
+
+impl RouteShare {
+    pub fn error(error: String) -> RouteShare {
+        RouteShare {
+            path: "".to_string(),
+            error,
+        }
+    }
 }
@@ -1,4 +1,6 @@
+use crate::db::cyclability_score::Closure;
 use askama::Template;
+use chrono::Duration;
 
 #[derive(PartialEq)]
 pub enum Category {
@@ -8,16 +10,22 @@ pub enum Category {
     Closed,
 }
 
-#[derive(Template)]
-#[template(path = "score_selector.html")]
-pub struct ScoreSelector {
-    category: Category,
-    score: f64,
-}
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Good,
+        Category::Problems,
+        Category::Dangerous,
+        Category::Closed,
+    ];
 
-impl ScoreSelector {
-    pub fn get_score_selector(score: f64) -> ScoreSelector {
-        let category = if score == 0.0 {
+    pub fn from_value(value: &str) -> Option<Category> {
+        Category::ALL
+            .into_iter()
+            .find(|category| category.value() == value)
+    }
+
+    pub fn from_score(score: f64) -> Category {
+        if score == 0.0 {
             Category::Closed
         } else if score <= 0.34 {
             Category::Dangerous
@@ -25,7 +33,134 @@ impl ScoreSelector {
             Category::Problems
         } else {
             Category::Good
-        };
-        ScoreSelector { score, category }
+        }
+    }
+
+    // stored in the filters of the webhooks
+    pub fn value(&self) -> &'static str {
+        match self {
+            Category::Good => "good",
+            Category::Problems => "problems",
+            Category::Dangerous => "dangerous",
+            Category::Closed => "closed",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Category::Good => "État normal",
+            Category::Problems => "Problème mineur",
+            Category::Dangerous => "Piste dangeureuse",
+            Category::Closed => "Fermé",
+        }
+    }
+}
+
+// The problems a rider can point out with a score, stored by value
+#[derive(PartialEq, Clone, Copy)]
+pub enum Issue {
+    Pothole,
+    Snow,
+    ParkedCars,
+    Construction,
+    BrokenGlass,
+    MissingSignage,
+    DangerousIntersection,
+}
+
+impl Issue {
+    pub const ALL: [Issue; 7] = [
+        Issue::Pothole,
+        Issue::Snow,
+        Issue::ParkedCars,
+        Issue::Construction,
+        Issue::BrokenGlass,
+        Issue::MissingSignage,
+        Issue::DangerousIntersection,
+    ];
+
+    pub fn from_value(value: &str) -> Option<Issue> {
+        Issue::ALL.into_iter().find(|issue| issue.value() == value)
+    }
+
+    pub fn value(&self) -> &'static str {
+        match self {
+            Issue::Pothole => "pothole",
+            Issue::Snow => "snow",
+            Issue::ParkedCars => "parked_cars",
+            Issue::Construction => "construction",
+            Issue::BrokenGlass => "broken_glass",
+            Issue::MissingSignage => "missing_signage",
+            Issue::DangerousIntersection => "dangerous_intersection",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Issue::Pothole => "Nid-de-poule",
+            Issue::Snow => "Neige",
+            Issue::ParkedCars => "Voitures stationnées",
+            Issue::Construction => "Travaux",
+            Issue::BrokenGlass => "Verre brisé",
+            Issue::MissingSignage => "Signalisation manquante",
+            Issue::DangerousIntersection => "Intersection dangereuse",
+        }
+    }
+}
+
+// The labels of the known values
+pub fn issue_labels(issues: &[String]) -> Vec<&'static str> {
+    issues
+        .iter()
+        .filter_map(|issue| Issue::from_value(issue))
+        .map(|issue| issue.label())
+        .collect()
+}
+
+#[derive(Template)]
+#[template(path = "score_selector.html")]
+pub struct ScoreSelector {
+    category: Category,
+    score: f64,
+    // value, label, checked
+    issues: Vec<(&'static str, &'static str, bool)>,
+    // the dates of the closure, yyyy-mm-dd or empty
+    closed_from: String,
+    closed_until: String,
+}
+
+impl ScoreSelector {
+    pub fn get_score_selector(score: f64, issues: &[String]) -> ScoreSelector {
+        let category = Category::from_score(score);
+        let issues = Issue::ALL
+            .iter()
+            .map(|issue| {
+                (
+                    issue.value(),
+                    issue.label(),
+                    issues.iter().any(|checked| checked == issue.value()),
+                )
+            })
+            .collect();
+        ScoreSelector {
+            score,
+            category,
+            issues,
+            closed_from: "".to_string(),
+            closed_until: "".to_string(),
+        }
+    }
+
+    // the closure ends at the start of the day after the last day
+    pub fn with_closure(mut self, closure: &Closure) -> ScoreSelector {
+        self.closed_from = closure
+            .from
+            .map(|from| from.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        self.closed_until = closure
+            .until
+            .map(|until| (until - Duration::days(1)).format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        self
     }
 }
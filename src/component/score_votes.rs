@@ -0,0 +1,69 @@
+use crate::auth::Userinfo;
+use crate::db::score_vote::ScoreVote;
+use crate::VeloinfoState;
+use askama::Template;
+use axum::extract::{Path, State};
+use axum_extra::extract::SignedCookieJar;
+use sqlx::Postgres;
+use std::collections::HashMap;
+
+#[derive(Template, Clone)]
+#[template(path = "score_votes.html", escape = "none")]
+pub struct ScoreVotes {
+    score_id: i32,
+    still_valid: i64,
+    not_valid: i64,
+    // only the logged in riders vote, a uuid cookie is free to mint
+    login_needed: bool,
+}
+
+impl ScoreVotes {
+    pub async fn get(score_id: i32, conn: &sqlx::Pool<Postgres>) -> ScoreVotes {
+        let (still_valid, not_valid) = ScoreVote::get_counts(score_id, conn).await;
+        ScoreVotes {
+            score_id,
+            still_valid,
+            not_valid,
+            login_needed: false,
+        }
+    }
+
+    // one for each of the score_ids
+    pub async fn get_many(
+        score_ids: &[i32],
+        conn: &sqlx::Pool<Postgres>,
+    ) -> HashMap<i32, ScoreVotes> {
+        let counts = ScoreVote::get_counts_by_ids(score_ids, conn).await;
+        score_ids
+            .iter()
+            .map(|score_id| {
+                let (still_valid, not_valid) = counts.get(score_id).copied().unwrap_or((0, 0));
+                (
+                    *score_id,
+                    ScoreVotes {
+                        score_id: *score_id,
+                        still_valid,
+                        not_valid,
+                        login_needed: false,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+pub async fn score_vote(
+    State(state): State<VeloinfoState>,
+    Path((score_id, still_valid)): Path<(i32, bool)>,
+    jar: SignedCookieJar,
+) -> ScoreVotes {
+    let Some(userinfo) = Userinfo::get(&jar) else {
+        let mut votes = ScoreVotes::get(score_id, &state.conn).await;
+        votes.login_needed = true;
+        return votes;
+    };
+    if let Err(e) = ScoreVote::vote(score_id, &userinfo.sub, still_valid, &state.conn).await {
+        eprintln!("Error while voting on the score {}: {}", score_id, e);
+    }
+    ScoreVotes::get(score_id, &state.conn).await
+}
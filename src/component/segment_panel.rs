@@ -1,16 +1,24 @@
 use super::{
-    info_panel::InfopanelContribution, score_circle::ScoreCircle, score_selector::ScoreSelector,
+    info_panel::InfopanelContribution,
+    score_circle::ScoreCircle,
+    score_selector::{Issue, ScoreSelector},
 };
+use crate::auth::is_admin;
 use crate::db::cycleway::{Cycleway, Node};
 use crate::db::edge::Edge;
 use crate::db::user::User;
-use crate::{db::cyclability_score::CyclabilityScore, VeloinfoState};
+use crate::{
+    db::cyclability_score::{Closure, CyclabilityScore},
+    VeloinfoState,
+};
 use askama::Template;
+use axum::body::Bytes;
 use axum::extract::multipart::Multipart;
 use axum::extract::{Path, State};
 use axum_extra::extract::cookie::Cookie;
-use axum_extra::extract::CookieJar;
+use axum_extra::extract::{CookieJar, SignedCookieJar};
 use axum_macros::debug_handler;
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
 use futures::future::join_all;
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -33,12 +41,17 @@ pub struct SegmentPanel {
     geom_json: String,
     fit_bounds: bool,
     user_name: String,
+    // the contribution shown, 0 for the current state of the ways
+    score_id: i32,
+    // the author can still correct the contribution
+    editable: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PostValue {
     pub score: f64,
     pub comment: String,
+    pub issues: Vec<String>,
     pub way_ids: String,
     pub photo: Option<i64>,
 }
@@ -55,7 +68,7 @@ lazy_static! {
 pub async fn segment_panel_post(
     State(state): State<VeloinfoState>,
     jar: CookieJar,
-    mut multipart: Multipart,
+    multipart: Multipart,
 ) -> (CookieJar, SegmentPanel) {
     jar.iter().for_each(|c| println!("cookie {:?}", c));
     let user_id = match jar.get("uuid") {
@@ -79,40 +92,22 @@ pub async fn segment_panel_post(
         None => None,
     };
 
-    let mut score = -1.;
-    let mut comment = "".to_string();
-    let mut way_ids = "".to_string();
-    let mut photo = None;
-    let mut user_name = "".to_string();
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let name = field.name().unwrap();
-        match name {
-            "score" => {
-                score = field
-                    .text()
-                    .await
-                    .unwrap_or("0".to_string())
-                    .parse::<f64>()
-                    .unwrap()
-            }
-            "comment" => comment = field.text().await.unwrap_or("".to_string()),
-            "way_ids" => way_ids = field.text().await.unwrap_or("".to_string()),
-            "photo" => {
-                photo = match field.bytes().await {
-                    Ok(b) => Some(b),
-                    Err(e) => {
-                        println!("Error getting bytes {:?}", e);
-                        None
-                    }
-                }
-            }
-            "user_name" => user_name = field.text().await.unwrap_or("".to_string()),
-            _ => (),
-        }
-    }
+    let ScoreForm {
+        score,
+        comment,
+        issues,
+        way_ids,
+        photo,
+        user_name,
+        mut closure,
+        not_cleared,
+    } = ScoreForm::parse(multipart).await;
     if let Some(user_id) = user_id {
         User::update(&user_id, &user_name, &state.conn).await;
     }
+    if score != 0.0 {
+        closure = Closure::default();
+    }
     let way_ids_i64 = RE_NUMBER
         .find_iter(way_ids.as_str())
         .map(|m| m.as_str().parse::<i64>().unwrap())
@@ -121,6 +116,7 @@ pub async fn segment_panel_post(
     let id = match CyclabilityScore::insert(
         &score,
         &Some(comment),
+        &issues,
         &way_ids_i64,
         &match photo.as_ref() {
             Some(_photo) => Some(IMAGE_DIR.to_string() + "/{}.jpeg"),
@@ -131,6 +127,8 @@ pub async fn segment_panel_post(
             None => None,
         },
         user_id,
+        &closure,
+        not_cleared,
         &state.conn,
     )
     .await
@@ -144,7 +142,7 @@ pub async fn segment_panel_post(
                     way_ids: way_ids.clone(),
                     score_circle: ScoreCircle { score },
                     segment_name: "".to_string(),
-                    score_selector: ScoreSelector::get_score_selector(score),
+                    score_selector: ScoreSelector::get_score_selector(score, &issues),
                     comment: "".to_string(),
                     edit: false,
                     history: vec![],
@@ -152,24 +150,250 @@ pub async fn segment_panel_post(
                     geom_json: "".to_string(),
                     fit_bounds: false,
                     user_name,
+                    score_id: 0,
+                    editable: false,
                 },
             );
         }
     };
 
     if let Some(photo) = photo {
-        let img = image::load_from_memory(&photo).unwrap();
-        let img = img.resize(1500, 1500, image::imageops::FilterType::Lanczos3);
-        img.save(IMAGE_DIR.to_string() + "/" + id.to_string().as_str() + ".jpeg")
-            .unwrap();
-        let img = img.resize(300, 300, image::imageops::FilterType::Lanczos3);
-        img.save(IMAGE_DIR.to_string() + "/" + id.to_string().as_str() + "_thumbnail.jpeg")
-            .unwrap();
+        save_photo(id, &photo);
     }
+    crate::webhook::enqueue(id, crate::webhook::SCORE_CREATED, &state.conn).await;
 
     (jar, segment_panel(state, way_ids).await)
 }
 
+// The fields of the score form, shared by a new contribution and its correction
+struct ScoreForm {
+    score: f64,
+    comment: String,
+    issues: Vec<String>,
+    way_ids: String,
+    photo: Option<Bytes>,
+    user_name: String,
+    closure: Closure,
+    not_cleared: bool,
+}
+
+impl ScoreForm {
+    async fn parse(mut multipart: Multipart) -> ScoreForm {
+        let mut form = ScoreForm {
+            score: -1.,
+            comment: "".to_string(),
+            issues: vec![],
+            way_ids: "".to_string(),
+            photo: None,
+            user_name: "".to_string(),
+            closure: Closure::default(),
+            not_cleared: false,
+        };
+        while let Some(field) = multipart.next_field().await.unwrap() {
+            let name = field.name().unwrap();
+            match name {
+                "score" => {
+                    form.score = field
+                        .text()
+                        .await
+                        .unwrap_or("0".to_string())
+                        .parse::<f64>()
+                        .unwrap()
+                }
+                "comment" => form.comment = field.text().await.unwrap_or("".to_string()),
+                // one field by checked issue
+                "issues" => {
+                    if let Some(issue) = Issue::from_value(&field.text().await.unwrap_or_default())
+                    {
+                        form.issues.push(issue.value().to_string());
+                    }
+                }
+                "way_ids" => form.way_ids = field.text().await.unwrap_or("".to_string()),
+                "photo" => {
+                    form.photo = match field.bytes().await {
+                        // an empty file input is still sent
+                        Ok(b) if b.is_empty() => None,
+                        Ok(b) => Some(b),
+                        Err(e) => {
+                            println!("Error getting bytes {:?}", e);
+                            None
+                        }
+                    }
+                }
+                "user_name" => form.user_name = field.text().await.unwrap_or("".to_string()),
+                "not_cleared" => form.not_cleared = true,
+                "closed_from" => {
+                    form.closure.from = parse_date(&field.text().await.unwrap_or_default(), 0)
+                }
+                // the closure lasts until the end of the last day
+                "closed_until" => {
+                    form.closure.until = parse_date(&field.text().await.unwrap_or_default(), 1)
+                }
+                _ => (),
+            }
+        }
+        form
+    }
+}
+
+fn save_photo(id: i32, photo: &Bytes) {
+    let img = image::load_from_memory(photo).unwrap();
+    let img = img.resize(1500, 1500, image::imageops::FilterType::Lanczos3);
+    img.save(IMAGE_DIR.to_string() + "/" + id.to_string().as_str() + ".jpeg")
+        .unwrap();
+    let img = img.resize(300, 300, image::imageops::FilterType::Lanczos3);
+    img.save(IMAGE_DIR.to_string() + "/" + id.to_string().as_str() + "_thumbnail.jpeg")
+        .unwrap();
+}
+
+// The uuid cookie identifies the author of a contribution
+pub fn cookie_user_id(jar: &CookieJar) -> Option<Uuid> {
+    jar.get("uuid")
+        .and_then(|uuid| Uuid::parse_str(uuid.value()).ok())
+}
+
+// A rider without cookie gets one
+pub fn cookie_user_id_or_new(jar: CookieJar) -> (CookieJar, Uuid) {
+    match cookie_user_id(&jar) {
+        Some(user_id) => (jar, user_id),
+        None => {
+            let uuid = Uuid::now_v7();
+            (
+                jar.add(
+                    Cookie::build(("uuid", uuid.to_string()))
+                        .path("/")
+                        .permanent(),
+                ),
+                uuid,
+            )
+        }
+    }
+}
+
+pub async fn segment_panel_score_edit(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+    jar: CookieJar,
+) -> SegmentPanel {
+    let user_id = cookie_user_id(&jar);
+    let mut segment_panel = segment_panel_score_id(&state.conn, id, true, user_id, false).await;
+    if let Some(user) = user_id {
+        if let Some(user) = User::get(&user, &state.conn).await {
+            segment_panel.user_name = user.name;
+        }
+    }
+    segment_panel
+}
+
+pub async fn segment_panel_score_post(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+    jar: CookieJar,
+    multipart: Multipart,
+) -> SegmentPanel {
+    let user_id = match cookie_user_id(&jar) {
+        Some(user_id) => user_id,
+        None => return segment_panel_score_id(&state.conn, id, false, None, false).await,
+    };
+    let ScoreForm {
+        score,
+        comment,
+        issues,
+        photo,
+        user_name,
+        mut closure,
+        ..
+    } = ScoreForm::parse(multipart).await;
+    if score != 0.0 {
+        closure = Closure::default();
+    }
+    let updated = match CyclabilityScore::update(
+        id,
+        &score,
+        &Some(comment),
+        &issues,
+        &photo
+            .as_ref()
+            .map(|_| format!("{}/{}.jpeg", IMAGE_DIR.as_str(), id)),
+        &photo
+            .as_ref()
+            .map(|_| format!("{}/{}_thumbnail.jpeg", IMAGE_DIR.as_str(), id)),
+        &user_id,
+        &closure,
+        &state.conn,
+    )
+    .await
+    {
+        Ok(updated) => updated,
+        Err(e) => {
+            eprintln!("Error while updating score: {}", e);
+            false
+        }
+    };
+    if updated {
+        User::update(&user_id, &user_name, &state.conn).await;
+        if let Some(photo) = photo {
+            save_photo(id, &photo);
+        }
+        crate::webhook::enqueue(id, crate::webhook::SCORE_UPDATED, &state.conn).await;
+    }
+
+    segment_panel_score_id(&state.conn, id, false, Some(user_id), false).await
+}
+
+pub async fn segment_panel_score_delete(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+    jar: CookieJar,
+) -> SegmentPanel {
+    let user_id = cookie_user_id(&jar);
+    let score = match CyclabilityScore::get_by_id(id, &state.conn).await {
+        Ok(score) => score,
+        Err(e) => {
+            eprintln!("Error while fetching score: {}", e);
+            return segment_panel_score_id(&state.conn, id, false, user_id, false).await;
+        }
+    };
+    let deleted = match user_id {
+        Some(user_id) => match CyclabilityScore::delete(id, &user_id, &state.conn).await {
+            Ok(deleted) => deleted,
+            Err(e) => {
+                eprintln!("Error while deleting score: {}", e);
+                false
+            }
+        },
+        None => false,
+    };
+    if !deleted {
+        return segment_panel_score_id(&state.conn, id, false, user_id, false).await;
+    }
+
+    for path in [&score.photo_path, &score.photo_path_thumbnail]
+        .into_iter()
+        .flatten()
+    {
+        if let Err(e) = std::fs::remove_file(path) {
+            eprintln!("Error while removing photo {}: {}", path, e);
+        }
+    }
+
+    let way_ids = score
+        .way_ids
+        .iter()
+        .map(|way_id| way_id.to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
+    segment_panel(state, way_ids).await
+}
+
+// Midnight of the date given by a date input, plus some days
+fn parse_date(date: &str, days: i64) -> Option<DateTime<Local>> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()? + Duration::days(days);
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+}
+
 pub async fn segment_panel_edit(
     State(state): State<VeloinfoState>,
     Path(way_ids): Path<String>,
@@ -252,7 +476,7 @@ pub async fn segment_panel_edit(
             score: way.score.unwrap_or(-1.),
         },
         segment_name,
-        score_selector: ScoreSelector::get_score_selector(way.score.unwrap_or(-1.)),
+        score_selector: ScoreSelector::get_score_selector(way.score.unwrap_or(-1.), &[]),
         comment: "".to_string(),
         edit: true,
         history,
@@ -260,6 +484,8 @@ pub async fn segment_panel_edit(
         geom_json,
         fit_bounds: false,
         user_name,
+        score_id: 0,
+        editable: false,
     };
 
     (jar, segment_panel)
@@ -324,14 +550,17 @@ pub async fn segment_panel(state: VeloinfoState, way_ids: String) -> SegmentPane
             },
         },
         segment_name,
-        score_selector: ScoreSelector::get_score_selector(if all_same_score {
-            match cycleways.first() {
-                Some(way) => way.score.unwrap_or(-1.),
-                None => -1.,
-            }
-        } else {
-            -1.
-        }),
+        score_selector: ScoreSelector::get_score_selector(
+            if all_same_score {
+                match cycleways.first() {
+                    Some(way) => way.score.unwrap_or(-1.),
+                    None => -1.,
+                }
+            } else {
+                -1.
+            },
+            &[],
+        ),
         comment: "".to_string(),
         edit: false,
         history,
@@ -339,6 +568,8 @@ pub async fn segment_panel(state: VeloinfoState, way_ids: String) -> SegmentPane
         geom_json: serde_json::to_string(&geom).unwrap_or("".to_string()),
         fit_bounds: false,
         user_name: "".to_string(),
+        score_id: 0,
+        editable: false,
     }
 }
 
@@ -398,11 +629,24 @@ pub struct SegmentPanelBigger {
     geom_json: String,
 }
 
-async fn segment_panel_score_id(conn: &sqlx::Pool<Postgres>, id: i32, edit: bool) -> SegmentPanel {
+async fn segment_panel_score_id(
+    conn: &sqlx::Pool<Postgres>,
+    id: i32,
+    edit: bool,
+    user_id: Option<Uuid>,
+    admin: bool,
+) -> SegmentPanel {
     let score = match CyclabilityScore::get_by_id(id, &conn).await {
-        Ok(score) => score,
-        Err(e) => {
-            eprintln!("Error while fetching score: {}", e);
+        // hidden by a moderator, only its author and the moderators see it
+        Ok(score)
+            if !score.hidden || admin || (score.user_id.is_some() && score.user_id == user_id) =>
+        {
+            score
+        }
+        result => {
+            if let Err(e) = result {
+                eprintln!("Error while fetching score: {}", e);
+            }
             CyclabilityScore {
                 id: 0,
                 name: Some(vec![]),
@@ -414,6 +658,8 @@ async fn segment_panel_score_id(conn: &sqlx::Pool<Postgres>, id: i32, edit: bool
                 photo_path_thumbnail: None,
                 geom: vec![],
                 user_id: None,
+                hidden: false,
+                issues: vec![],
             }
         }
     };
@@ -452,19 +698,31 @@ async fn segment_panel_score_id(conn: &sqlx::Pool<Postgres>, id: i32, edit: bool
 
     let history = InfopanelContribution::get_history(&score.way_ids, conn).await;
     let photo_ids = CyclabilityScore::get_photo_by_way_ids(&score.way_ids, &conn).await;
+    let editable = score.editable_by(&user_id);
+    let mut score_selector = ScoreSelector::get_score_selector(score.score, &score.issues);
+    // an empty date clears it, the form shows the current ones
+    if edit && editable {
+        match CyclabilityScore::get_closure(score.id, conn).await {
+            Ok(closure) => score_selector = score_selector.with_closure(&closure),
+            Err(e) => eprintln!("Error while fetching the closure of {}: {}", score.id, e),
+        }
+    }
 
     SegmentPanel {
         way_ids,
         score_circle: ScoreCircle { score: score.score },
         segment_name,
-        score_selector: ScoreSelector::get_score_selector(score.score),
+        score_selector,
         comment: score.comment.unwrap_or("".to_string()),
-        edit,
+        // only the author gets the form
+        edit: edit && editable,
         history,
         photo_ids,
         geom_json,
         fit_bounds: true,
         user_name: "".to_string(),
+        score_id: score.id,
+        editable,
     }
 }
 
@@ -517,7 +775,7 @@ pub async fn segment_panel_lng_lat(
             score: way.score.unwrap_or(-1.),
         },
         segment_name,
-        score_selector: ScoreSelector::get_score_selector(way.score.unwrap_or(-1.)),
+        score_selector: ScoreSelector::get_score_selector(way.score.unwrap_or(-1.), &[]),
         comment: "".to_string(),
         edit: false,
         history,
@@ -525,6 +783,8 @@ pub async fn segment_panel_lng_lat(
         geom_json: serde_json::to_string(&node.geom).unwrap_or("".to_string()),
         fit_bounds: false,
         user_name: "".to_string(),
+        score_id: 0,
+        editable: false,
     };
 
     info_panel
@@ -533,8 +793,17 @@ pub async fn segment_panel_lng_lat(
 pub async fn select_score_id(
     State(state): State<VeloinfoState>,
     Path(id): Path<i32>,
+    jar: CookieJar,
+    signed_jar: SignedCookieJar,
 ) -> SegmentPanel {
-    segment_panel_score_id(&state.conn, id, false).await
+    segment_panel_score_id(
+        &state.conn,
+        id,
+        false,
+        cookie_user_id(&jar),
+        is_admin(&signed_jar),
+    )
+    .await
 }
 
 #[derive(Template)]
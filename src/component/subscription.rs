@@ -0,0 +1,237 @@
+use crate::auth::Userinfo;
+use crate::component::segment_panel::cookie_user_id_or_new;
+use crate::db::subscription::Subscription;
+use crate::VeloinfoState;
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::Form;
+use axum_extra::extract::{CookieJar, SignedCookieJar};
+use serde::Deserialize;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "subscription.html")]
+pub struct SubscriptionForm {
+    email: String,
+    way_ids: String,
+    // lng1, lat1, lng2, lat2 of the followed area
+    area: Option<(f64, f64, f64, f64)>,
+    message: String,
+    sent: bool,
+}
+
+// The links of the emails show a form on GET and act on POST only,
+// the mail scanners open the links before the rider does
+#[derive(Template)]
+#[template(path = "subscription_token.html")]
+pub struct SubscriptionToken {
+    action: TokenAction,
+    token: String,
+    done: bool,
+    found: bool,
+}
+
+#[derive(Clone, Copy)]
+enum TokenAction {
+    Confirm,
+    Unsubscribe,
+}
+
+impl TokenAction {
+    fn path(&self) -> &'static str {
+        match self {
+            TokenAction::Confirm => "/subscription/confirm",
+            TokenAction::Unsubscribe => "/unsubscribe",
+        }
+    }
+
+    fn question(&self) -> &'static str {
+        match self {
+            TokenAction::Confirm => {
+                "Recevoir chaque jour les nouvelles contributions sur les voies suivies ?"
+            }
+            TokenAction::Unsubscribe => "Ne plus recevoir les nouvelles contributions ?",
+        }
+    }
+
+    fn button(&self) -> &'static str {
+        match self {
+            TokenAction::Confirm => "confirmer",
+            TokenAction::Unsubscribe => "se désabonner",
+        }
+    }
+
+    fn done(&self) -> &'static str {
+        match self {
+            TokenAction::Confirm => "Vous recevrez chaque jour les nouvelles contributions.",
+            TokenAction::Unsubscribe => "Vous ne recevrez plus les nouvelles contributions.",
+        }
+    }
+}
+
+impl SubscriptionToken {
+    fn form(action: TokenAction, token: String) -> SubscriptionToken {
+        SubscriptionToken {
+            action,
+            found: Uuid::parse_str(&token).is_ok(),
+            token,
+            done: false,
+        }
+    }
+
+    async fn post(
+        action: TokenAction,
+        token: String,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> SubscriptionToken {
+        let found = match Uuid::parse_str(&token) {
+            Ok(uuid) => {
+                let result = match action {
+                    TokenAction::Confirm => Subscription::confirm_by_token(&uuid, conn).await,
+                    TokenAction::Unsubscribe => Subscription::delete_by_token(&uuid, conn).await,
+                };
+                match result {
+                    Ok(found) => found,
+                    Err(e) => {
+                        eprintln!("Error while updating the subscription: {}", e);
+                        false
+                    }
+                }
+            }
+            Err(_) => false,
+        };
+        SubscriptionToken {
+            action,
+            token,
+            done: true,
+            found,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriptionPost {
+    email: String,
+    way_ids: Option<String>,
+    lng1: Option<f64>,
+    lat1: Option<f64>,
+    lng2: Option<f64>,
+    lat2: Option<f64>,
+}
+
+// the email of the logged in rider is suggested
+fn user_email(jar: &SignedCookieJar) -> String {
+    Userinfo::get(jar)
+        .map(|userinfo| userinfo.email)
+        .unwrap_or_default()
+}
+
+pub async fn subscription_ways(
+    Path(way_ids): Path<String>,
+    jar: SignedCookieJar,
+) -> SubscriptionForm {
+    SubscriptionForm {
+        email: user_email(&jar),
+        way_ids,
+        area: None,
+        message: "".to_string(),
+        sent: false,
+    }
+}
+
+pub async fn subscription_area(
+    Path((lng1, lat1, lng2, lat2)): Path<(f64, f64, f64, f64)>,
+    jar: SignedCookieJar,
+) -> SubscriptionForm {
+    SubscriptionForm {
+        email: user_email(&jar),
+        way_ids: "".to_string(),
+        area: Some((lng1, lat1, lng2, lat2)),
+        message: "".to_string(),
+        sent: false,
+    }
+}
+
+pub async fn subscription_post(
+    State(state): State<VeloinfoState>,
+    jar: CookieJar,
+    Form(form): Form<SubscriptionPost>,
+) -> (CookieJar, SubscriptionForm) {
+    let way_ids = form
+        .way_ids
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|way_id| way_id.parse::<i64>().ok())
+        .collect::<Vec<i64>>();
+    let area = match (form.lng1, form.lat1, form.lng2, form.lat2) {
+        (Some(lng1), Some(lat1), Some(lng2), Some(lat2)) => Some((lng1, lat1, lng2, lat2)),
+        _ => None,
+    };
+    let mut subscription = SubscriptionForm {
+        email: form.email.clone(),
+        way_ids: way_ids
+            .iter()
+            .map(|way_id| way_id.to_string())
+            .collect::<Vec<String>>()
+            .join(" "),
+        area,
+        message: "".to_string(),
+        sent: false,
+    };
+    if form.email.parse::<lettre::Address>().is_err() {
+        subscription.message = "Courriel invalide".to_string();
+        return (jar, subscription);
+    }
+    let way_ids = if way_ids.is_empty() {
+        None
+    } else {
+        Some(&way_ids)
+    };
+    if way_ids.is_none() && area.is_none() {
+        eprintln!("Subscription without ways or area");
+        subscription.message = "Rien à suivre".to_string();
+        return (jar, subscription);
+    }
+
+    let (jar, user_id) = cookie_user_id_or_new(jar);
+    match Subscription::insert(&form.email, way_ids, area, &user_id, &state.conn).await {
+        Ok(pending) => {
+            if crate::digest::send_confirmation(&pending).await {
+                subscription.sent = true;
+                subscription.message =
+                    "Confirmez votre abonnement avec le lien reçu par courriel.".to_string();
+            } else {
+                subscription.message = "Erreur, réessayez plus tard".to_string();
+            }
+        }
+        Err(e) => {
+            eprintln!("Error while inserting the subscription: {}", e);
+            subscription.message = "Erreur, réessayez plus tard".to_string();
+        }
+    }
+    (jar, subscription)
+}
+
+pub async fn subscription_confirm(Path(token): Path<String>) -> SubscriptionToken {
+    SubscriptionToken::form(TokenAction::Confirm, token)
+}
+
+pub async fn subscription_confirm_post(
+    State(state): State<VeloinfoState>,
+    Path(token): Path<String>,
+) -> SubscriptionToken {
+    SubscriptionToken::post(TokenAction::Confirm, token, &state.conn).await
+}
+
+pub async fn unsubscribe(Path(token): Path<String>) -> SubscriptionToken {
+    SubscriptionToken::form(TokenAction::Unsubscribe, token)
+}
+
+// also the one click unsubscribe of the mail clients, RFC 8058
+pub async fn unsubscribe_post(
+    State(state): State<VeloinfoState>,
+    Path(token): Path<String>,
+) -> SubscriptionToken {
+    SubscriptionToken::post(TokenAction::Unsubscribe, token, &state.conn).await
+}
@@ -0,0 +1,158 @@
+use crate::auth::is_admin;
+use crate::component::score_selector::Category;
+use crate::db::webhook::Webhook;
+use crate::VeloinfoState;
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Form;
+use axum_extra::extract::SignedCookieJar;
+use chrono::Locale;
+use chrono_tz::America::Montreal;
+use sqlx::Postgres;
+
+// The urls are written by the admins, they are escaped anyway
+#[derive(Template)]
+#[template(path = "webhook_panel.html")]
+pub struct WebhookPanel {
+    webhooks: Vec<WebhookLine>,
+    // value, label
+    categories: Vec<(&'static str, &'static str)>,
+    message: String,
+}
+
+struct WebhookLine {
+    id: i32,
+    url: String,
+    categories: String,
+    bbox: String,
+    created_at: String,
+}
+
+impl WebhookPanel {
+    async fn get(message: &str, conn: &sqlx::Pool<Postgres>) -> WebhookPanel {
+        let webhooks = match Webhook::get_all(conn).await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                eprintln!("Error getting the webhooks {:?}", e);
+                vec![]
+            }
+        };
+        WebhookPanel {
+            webhooks: webhooks
+                .into_iter()
+                .map(|webhook| WebhookLine {
+                    id: webhook.id,
+                    url: webhook.url,
+                    categories: match webhook.categories {
+                        Some(categories) => categories
+                            .iter()
+                            .filter_map(|category| Category::from_value(category))
+                            .map(|category| category.label())
+                            .collect::<Vec<&str>>()
+                            .join(", "),
+                        None => "Toutes".to_string(),
+                    },
+                    bbox: webhook.bbox.unwrap_or("Partout".to_string()),
+                    created_at: webhook
+                        .created_at
+                        .with_timezone(&Montreal)
+                        .format_localized("%d %B %Y", Locale::fr_CA)
+                        .to_string(),
+                })
+                .collect(),
+            categories: Category::ALL
+                .iter()
+                .map(|category| (category.value(), category.label()))
+                .collect(),
+            message: message.to_string(),
+        }
+    }
+}
+
+pub async fn webhook_panel(
+    State(state): State<VeloinfoState>,
+    jar: SignedCookieJar,
+) -> Result<WebhookPanel, StatusCode> {
+    if !is_admin(&jar) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(WebhookPanel::get("", &state.conn).await)
+}
+
+// url, secret, the checked categories and an optional bbox as lng1,lat1,lng2,lat2
+pub async fn webhook_post(
+    State(state): State<VeloinfoState>,
+    jar: SignedCookieJar,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<WebhookPanel, StatusCode> {
+    if !is_admin(&jar) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    let url = field("url");
+    let secret = field("secret");
+    let categories = fields
+        .iter()
+        .filter(|(key, _)| key == "categories")
+        .filter_map(|(_, value)| Category::from_value(value))
+        .map(|category| category.value().to_string())
+        .collect::<Vec<String>>();
+    let bbox = field("bbox");
+
+    if reqwest::Url::parse(&url).is_err() {
+        return Ok(WebhookPanel::get("Adresse invalide", &state.conn).await);
+    }
+    if secret.is_empty() {
+        return Ok(WebhookPanel::get("Le secret est requis", &state.conn).await);
+    }
+    let area = if bbox.is_empty() {
+        None
+    } else {
+        match bbox
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+        {
+            Ok(bbox) if bbox.len() == 4 => Some((bbox[0], bbox[1], bbox[2], bbox[3])),
+            _ => {
+                return Ok(
+                    WebhookPanel::get("La zone doit être lng1,lat1,lng2,lat2", &state.conn).await,
+                )
+            }
+        }
+    };
+    // no category checked is every category
+    let categories = if categories.is_empty() {
+        None
+    } else {
+        Some(&categories)
+    };
+
+    if let Err(e) = Webhook::insert(&url, &secret, categories, area, &state.conn).await {
+        eprintln!("Error while inserting the webhook: {}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    Ok(WebhookPanel::get("", &state.conn).await)
+}
+
+pub async fn webhook_delete(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<i32>,
+    jar: SignedCookieJar,
+) -> Result<WebhookPanel, StatusCode> {
+    if !is_admin(&jar) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Err(e) = Webhook::delete(id, &state.conn).await {
+        eprintln!("Error while deleting the webhook {}: {}", id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    Ok(WebhookPanel::get("", &state.conn).await)
+}
@@ -1,20 +1,46 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Duration, Local};
 use regex::Regex;
+use serde::Serialize;
 use sqlx::{Postgres, Row};
+use std::collections::HashMap;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(sqlx::FromRow, Debug)]
+// How long the author of a contribution can still correct or delete it
+pub const EDIT_DELAY_HOURS: i64 = 24;
+
+#[derive(sqlx::FromRow, Debug, Serialize, ToSchema)]
 pub struct CyclabilityScore {
     pub id: i32,
     pub name: Option<Vec<Option<String>>>,
     pub score: f64,
     pub comment: Option<String>,
+    // the values of `Issue`
+    pub issues: Vec<String>,
     pub way_ids: Vec<i64>,
     pub created_at: DateTime<Local>,
+    // paths on the server, the photos are served from /images/
+    #[serde(skip)]
     pub photo_path: Option<String>,
+    #[serde(skip)]
     pub photo_path_thumbnail: Option<String>,
+    // lng, lat
+    #[schema(value_type = Vec<Vec<f64>>)]
     pub geom: Vec<[f64; 2]>,
+    // the uuid of the user is also its cookie
+    #[serde(skip)]
     pub user_id: Option<Uuid>,
+    // hidden by a moderator, only the moderation panel shows it
+    #[serde(skip)]
+    pub hidden: bool,
+}
+
+// The time window of a closure, the route avoids the way only inside of it.
+// Both ends empty is a closure until the next score.
+#[derive(Debug, Default)]
+pub struct Closure {
+    pub from: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -23,20 +49,49 @@ pub struct CyclabilityScoreDb {
     pub name: Option<Vec<Option<String>>>,
     pub score: f64,
     pub comment: Option<String>,
+    pub issues: Vec<String>,
     pub way_ids: Vec<i64>,
     pub created_at: DateTime<Local>,
     pub photo_path: Option<String>,
     pub photo_path_thumbnail: Option<String>,
     pub geom: String,
     pub user_id: Option<Uuid>,
+    pub hidden: bool,
 }
 
 impl CyclabilityScore {
+    // The id of the last score of each way
+    pub async fn get_last_ids(
+        way_ids: &Vec<i64>,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> HashMap<i64, i32> {
+        let ids: Vec<(i64, i32)> = match sqlx::query_as(
+            r#"select distinct on (way_id) way_id, cs.id
+               from cyclability_score cs, unnest(cs.way_ids) way_id
+               where way_id = any($1)
+               and not cs.hidden
+               order by way_id, cs.created_at desc"#,
+        )
+        .bind(way_ids)
+        .fetch_all(conn)
+        .await
+        {
+            Ok(ids) => ids,
+            Err(e) => {
+                eprintln!("Error while fetching the last scores: {}", e);
+                vec![]
+            }
+        };
+        ids.into_iter().collect()
+    }
+
+    // Only the contributions with the issue if it is given
     pub async fn get_recents(
         lng1: f64,
         lat1: f64,
         lng2: f64,
         lat2: f64,
+        issue: Option<&str>,
         conn: &sqlx::Pool<Postgres>,
     ) -> Result<Vec<CyclabilityScore>, sqlx::Error> {
         let cs: Vec<CyclabilityScoreDb> = sqlx::query_as(
@@ -44,14 +99,18 @@ impl CyclabilityScore {
                         cs.name,
                         cs.score, 
                         cs.comment, 
+                        cs.issues,
                         cs.way_ids, 
                         cs.created_at, 
                         cs.photo_path, 
                         cs.photo_path_thumbnail,
                         ST_AsText(ST_Transform(geom, 4326)) as geom,
-                        cs.user_id
+                        cs.user_id,
+                        cs.hidden
                from cyclability_score cs
                where geom && ST_Transform(st_makeenvelope($1, $2, $3, $4, 4326), 3857)
+               and not cs.hidden
+               and ($5::text is null or $5 = any(cs.issues))
                order by cs.created_at desc
                limit 100"#,
         )
@@ -59,6 +118,7 @@ impl CyclabilityScore {
         .bind(lat1)
         .bind(lng2)
         .bind(lat2)
+        .bind(issue)
         .fetch_all(conn)
         .await?;
 
@@ -75,13 +135,16 @@ impl CyclabilityScore {
                       ST_AsText(ST_Transform(geom, 4326)) as geom,
                       score, 
                       comment, 
+                      issues,
                       way_ids, 
                       created_at, 
                       photo_path, 
                       photo_path_thumbnail,
-                      user_id
+                      user_id,
+                      hidden
                from cyclability_score
                where way_ids = $1
+               and not hidden
                order by created_at desc
                limit 100"#,
         )
@@ -99,6 +162,8 @@ impl CyclabilityScore {
         cs.iter().map(|c| c.into()).collect()
     }
 
+    // The hidden scores too, for their author and the moderators.
+    // The public paths check `hidden`.
     pub async fn get_by_id(
         id: i32,
         conn: &sqlx::Pool<Postgres>,
@@ -109,11 +174,13 @@ impl CyclabilityScore {
                       ST_AsText(ST_Transform(s.geom, 4326)) as geom, 
                       score, 
                       comment, 
+                      issues,
                       way_ids, 
                       created_at, 
                       photo_path, 
                       photo_path_thumbnail,
-                      user_id
+                      user_id,
+                      hidden
                from cyclability_score s
                join cycleway_way on way_id = any(way_ids)
                where id = $1"#,
@@ -134,13 +201,16 @@ impl CyclabilityScore {
                     ST_AsText(ST_Transform(s.geom, 4326)) as geom, 
                     score, 
                     comment, 
+                    issues,
                     way_ids, 
                     created_at, 
                     photo_path, 
                     photo_path_thumbnail,
-                    user_id
+                    user_id,
+                    hidden
                from cyclability_score s
                where way_ids = $1
+               and not hidden
                order by created_at desc"#,
         )
         .bind(way_ids)
@@ -156,6 +226,7 @@ impl CyclabilityScore {
                from cyclability_score
                where way_ids && $1
                and photo_path_thumbnail is not null
+               and not hidden
                order by created_at desc"#,
         )
         .bind(way_ids)
@@ -166,22 +237,26 @@ impl CyclabilityScore {
         result.iter().map(|photo| photo.get(0)).collect()
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn insert(
         score: &f64,
         comment: &Option<String>,
+        issues: &Vec<String>,
         way_ids: &Vec<i64>,
         photo_path: &Option<String>,
         photo_path_thumbnail: &Option<String>,
         user_id: Option<Uuid>,
+        closure: &Closure,
+        not_cleared: bool,
         conn: &sqlx::Pool<Postgres>,
     ) -> Result<i32, sqlx::Error> {
         let id: i32 = sqlx::query(
             r#"INSERT INTO cyclability_score 
-                    (way_ids, score, comment, photo_path, photo_path_thumbnail, name, geom, user_id) 
-                    SELECT $1, $2, $3, $4, $5, array_agg(cw.name), ST_Union(cw.geom), $6
+                    (way_ids, score, comment, photo_path, photo_path_thumbnail, name, geom, user_id, closed_from, closed_until, not_cleared, issues) 
+                    SELECT $1, $2, $3, $4, $5, array_agg(cw.name), ST_Union(cw.geom), $6, $7, $8, $9, $10
                     from cycleway_way cw
                     where cw.way_id = any($1)
-                    group by $1, $2, $3, $4, $5, $6
+                    group by $1, $2, $3, $4, $5, $6, $7, $8, $9, $10
                     RETURNING id"#,
         )
         .bind(way_ids)
@@ -190,6 +265,10 @@ impl CyclabilityScore {
         .bind(&photo_path)
         .bind(&photo_path_thumbnail)
         .bind(&user_id)
+        .bind(closure.from)
+        .bind(closure.until)
+        .bind(not_cleared)
+        .bind(issues)
         .fetch_one(conn)
         .await?
         .get(0);
@@ -211,6 +290,163 @@ impl CyclabilityScore {
             .await?;
         };
 
+        Self::refresh(conn).await?;
+
+        Ok(id)
+    }
+
+    // prefills the correction form
+    pub async fn get_closure(id: i32, conn: &sqlx::Pool<Postgres>) -> Result<Closure, sqlx::Error> {
+        let (from, until): (Option<DateTime<Local>>, Option<DateTime<Local>>) = sqlx::query_as(
+            r#"select closed_from, closed_until
+               from cyclability_score
+               where id = $1"#,
+        )
+        .bind(id)
+        .fetch_one(conn)
+        .await?;
+        Ok(Closure { from, until })
+    }
+
+    // The author can correct the score, the comment, the issues and the photo of a
+    // contribution during EDIT_DELAY_HOURS. Returns false if nothing was updated.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update(
+        id: i32,
+        score: &f64,
+        comment: &Option<String>,
+        issues: &Vec<String>,
+        photo_path: &Option<String>,
+        photo_path_thumbnail: &Option<String>,
+        user_id: &Uuid,
+        closure: &Closure,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"UPDATE cyclability_score
+                    SET score = $2,
+                    comment = $3,
+                    issues = $10,
+                    photo_path = coalesce($4, photo_path),
+                    photo_path_thumbnail = coalesce($5, photo_path_thumbnail),
+                    closed_from = case when $2 = 0 then $7 end,
+                    closed_until = case when $2 = 0 then $8 end
+                    WHERE id = $1
+                    AND user_id = $6
+                    AND created_at > now() - make_interval(hours => $9)"#,
+        )
+        .bind(id)
+        .bind(score)
+        .bind(comment)
+        .bind(photo_path)
+        .bind(photo_path_thumbnail)
+        .bind(user_id)
+        .bind(closure.from)
+        .bind(closure.until)
+        .bind(EDIT_DELAY_HOURS as i32)
+        .bind(issues)
+        .execute(conn)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        Self::refresh(conn).await?;
+
+        Ok(true)
+    }
+
+    // Same rules as update
+    pub async fn delete(
+        id: i32,
+        user_id: &Uuid,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"DELETE FROM cyclability_score
+               WHERE id = $1
+               AND user_id = $2
+               AND created_at > now() - make_interval(hours => $3)"#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(EDIT_DELAY_HOURS as i32)
+        .execute(conn)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        Self::refresh(conn).await?;
+
+        Ok(true)
+    }
+
+    // The reported contributions first, then the last ones with a photo,
+    // hidden or not, for the moderators
+    pub async fn get_moderation(
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<Vec<CyclabilityScore>, sqlx::Error> {
+        let cs: Vec<CyclabilityScoreDb> = sqlx::query_as(
+            r#"select id,
+                      name,
+                      ST_AsText(ST_Transform(geom, 4326)) as geom,
+                      score,
+                      comment,
+                      issues,
+                      way_ids,
+                      created_at,
+                      photo_path,
+                      photo_path_thumbnail,
+                      user_id,
+                      hidden
+               from cyclability_score cs,
+               lateral (
+                    select exists (
+                        select 1 from score_report sr
+                        where sr.cyclability_score_id = cs.id
+                        and not sr.reviewed
+                    ) as reported
+               ) r
+               where photo_path_thumbnail is not null
+               or hidden
+               or reported
+               order by reported desc, created_at desc
+               limit 100"#,
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(cs.iter().map(|c| c.into()).collect())
+    }
+
+    pub async fn set_hidden(
+        id: i32,
+        hidden: bool,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"UPDATE cyclability_score
+                    SET hidden = $2
+                    WHERE id = $1"#,
+        )
+        .bind(id)
+        .bind(hidden)
+        .execute(conn)
+        .await?;
+
+        Self::refresh(conn).await
+    }
+
+    pub fn editable_by(&self, user_id: &Option<Uuid>) -> bool {
+        user_id.is_some()
+            && self.user_id == *user_id
+            && self.created_at > Local::now() - Duration::hours(EDIT_DELAY_HOURS)
+    }
+
+    // bike_path is refreshed before answering so the map shows the change,
+    // the routing views follow in the background
+    async fn refresh(conn: &sqlx::Pool<Postgres>) -> Result<(), sqlx::Error> {
         sqlx::query(r#"REFRESH MATERIALIZED VIEW bike_path"#)
             .execute(conn)
             .await?;
@@ -225,9 +461,10 @@ impl CyclabilityScore {
                 .execute(&conn)
                 .await
                 .unwrap();
+            crate::graph::load(&conn).await;
         });
 
-        Ok(id)
+        Ok(())
     }
 }
 
@@ -248,12 +485,14 @@ impl From<&CyclabilityScoreDb> for CyclabilityScore {
             name: response.name.clone(),
             score: response.score,
             comment: response.comment.clone(),
+            issues: response.issues.clone(),
             way_ids: response.way_ids.clone(),
             created_at: response.created_at,
             photo_path: response.photo_path.clone(),
             photo_path_thumbnail: response.photo_path_thumbnail.clone(),
             geom: points,
             user_id: response.user_id,
+            hidden: response.hidden,
         }
     }
 }
@@ -2,11 +2,15 @@ use anyhow::Result;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sqlx::Postgres;
+use std::collections::HashMap;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Cycleway {
     pub name: Option<String>,
     pub way_id: i64,
+    // lng, lat
+    #[schema(value_type = Vec<Vec<f64>>)]
     pub geom: Vec<[f64; 2]>,
     pub source: i64,
     pub target: i64,
@@ -72,6 +76,7 @@ impl Cycleway {
                     select *
                     from cyclability_score 
                     where $1 = any(way_ids)
+                    and not hidden
                     order by created_at desc
                     limit 1
                ) cs on way_id = any(cs.way_ids)
@@ -151,6 +156,64 @@ impl Cycleway {
         };
         Ok(response.into())
     }
+
+    // The center of the best scored cycleway near a point
+    pub async fn find_best_near(
+        lng: &f64,
+        lat: &f64,
+        radius: &f64,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Option<(f64, f64)> {
+        match sqlx::query_as(
+            r#"select
+                ST_X(ST_Transform(ST_Centroid(geom), 4326)) as lng,
+                ST_Y(ST_Transform(ST_Centroid(geom), 4326)) as lat
+               from last_cycleway_score
+               where score > 0.67
+               and ST_DWithin(geom, ST_Transform(ST_SetSRID(ST_MakePoint($1, $2), 4326), 3857), $3)
+               order by score desc, geom <-> ST_Transform(ST_SetSRID(ST_MakePoint($1, $2), 4326), 3857)
+               limit 1"#,
+        )
+        .bind(lng)
+        .bind(lat)
+        .bind(radius)
+        .fetch_optional(conn)
+        .await
+        {
+            Ok(point) => point,
+            Err(e) => {
+                eprintln!("Error while fetching the best cycleway: {}", e);
+                None
+            }
+        }
+    }
+
+    // The name of each way, the cycleway name takes precedence over the street name
+    pub async fn get_names(
+        way_ids: &Vec<i64>,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> HashMap<i64, String> {
+        let names: Vec<(i64, Option<String>)> = match sqlx::query_as(
+            r#"select aw.way_id, coalesce(cw.name, aw.name) as name
+               from all_way aw
+               left join cycleway_way cw on cw.way_id = aw.way_id
+               where aw.way_id = any($1)"#,
+        )
+        .bind(way_ids)
+        .fetch_all(conn)
+        .await
+        {
+            Ok(names) => names,
+            Err(e) => {
+                eprintln!("Error while fetching way names: {}", e);
+                vec![]
+            }
+        };
+        names
+            .into_iter()
+            .filter_map(|(way_id, name)| Some((way_id, name?)))
+            .collect()
+    }
 }
 
 impl From<CyclewayDb> for Cycleway {
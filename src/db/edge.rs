@@ -1,15 +1,166 @@
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use sqlx::Postgres;
+use sqlx::{Postgres, Row};
+use utoipa::ToSchema;
 
 use super::cycleway::{Node, NodeDb};
+use super::routing_profile::RoutingProfile;
 
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
     pub way_id: i64,
     pub node_id: i64,
     pub length: f64,
+    pub score: Option<f64>,
+    pub cycleway: bool,
+    // meters above sea level when an elevation model is loaded
+    pub elevation: Option<f64>,
+}
+
+// The cost model used to weight the edges when routing.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteKind {
+    #[default]
+    Safest,
+    Shortest,
+    Balanced,
+}
+
+impl RouteKind {
+    pub fn all() -> [RouteKind; 3] {
+        [RouteKind::Safest, RouteKind::Balanced, RouteKind::Shortest]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RouteKind::Safest => "Le plus sécuritaire",
+            RouteKind::Shortest => "Le plus court",
+            RouteKind::Balanced => "Équilibré",
+        }
+    }
+
+    pub fn param(&self) -> &'static str {
+        match self {
+            RouteKind::Safest => "safest",
+            RouteKind::Shortest => "shortest",
+            RouteKind::Balanced => "balanced",
+        }
+    }
+
+    // The shortest route still avoids the roads where bikes are not allowed,
+    // the closed ways and the oneways.
+    fn cost(&self) -> &'static str {
+        match self {
+            RouteKind::Safest => "cost",
+            RouteKind::Shortest => {
+                "case when cost_road < 20 and coalesce(score, 1) != 0 then st_length(geom) else cost end"
+            }
+            RouteKind::Balanced => "sqrt(cost * st_length(geom))",
+        }
+    }
+
+    fn reverse_cost(&self) -> &'static str {
+        match self {
+            RouteKind::Safest => "reverse_cost",
+            RouteKind::Shortest => {
+                "case when oneway then reverse_cost when cost_road < 20 and coalesce(score, 1) != 0 then st_length(geom) else reverse_cost end"
+            }
+            RouteKind::Balanced => "sqrt(reverse_cost * st_length(geom))",
+        }
+    }
+
+    // Same as `cost` and `reverse_cost` for the in memory graph
+    pub fn costs(
+        &self,
+        cost: f64,
+        reverse_cost: f64,
+        cost_road: f64,
+        length: f64,
+        score: Option<f64>,
+        oneway: bool,
+    ) -> (f64, f64) {
+        match self {
+            RouteKind::Safest => (cost, reverse_cost),
+            RouteKind::Shortest => {
+                let shortest = cost_road < 20.0 && score != Some(0.0);
+                (
+                    if shortest { length } else { cost },
+                    if shortest && !oneway {
+                        length
+                    } else {
+                        reverse_cost
+                    },
+                )
+            }
+            RouteKind::Balanced => ((cost * length).sqrt(), (reverse_cost * length).sqrt()),
+        }
+    }
+
+    // The edges weighted by the cost of the kind and by the routing profile.
+    // It is a FORMAT template taking the profile id, the departure time for the
+    // closures limited in time and the winter, and the bounding box.
+    fn edges(&self) -> String {
+        format!(
+            r#"SELECT e.id,
+                source,
+                target,
+                x1,
+                y1,
+                x2,
+                y2,
+                {cost} * w.weight * s.factor * c.factor * v.factor * h.factor as cost,
+                case
+                    when oneway and p.contraflow_factor is not null
+                        then {cost} * w.weight * s.factor * c.factor * v.factor * h.factor * p.contraflow_factor
+                    else {reverse_cost} * w.weight * s.factor * c.factor * v.factor * h.factor
+                end as reverse_cost
+                from edge e
+                join routing_profile p on p.id = %s
+                cross join (select %L::timestamptz as departure) d
+                cross join lateral (
+                    select coalesce(exp(sum(ln(rpw.weight))), 1) as weight
+                    from routing_profile_weight rpw
+                    where rpw.profile_id = p.id
+                    and e.tags->>rpw.tag = rpw.value
+                ) w
+                cross join lateral (
+                    select case
+                        when score > 0 then power(1 / decayed_score, p.score_penalty)
+                        else 1
+                    end as factor
+                ) s
+                cross join lateral (
+                    select case
+                        when score = 0
+                            and (closed_from is not null or closed_until is not null)
+                            and coalesce(closed_from <= d.departure, true)
+                            and coalesce(closed_until > d.departure, true)
+                            then 1 / 0.001
+                        else 1
+                    end as factor
+                ) c
+                cross join lateral (
+                    select case
+                        when extract(month from d.departure) in (12, 1, 2, 3) then winter_factor
+                        else 1
+                    end as factor
+                ) v
+                cross join lateral (
+                    select case
+                        when hazard and p.hazard_factor is not null then p.hazard_factor
+                        else 1
+                    end as factor
+                ) h
+                where target is not null
+                and cost is not null
+                and geom && ST_Transform(ST_MakeEnvelope(%s, %s, %s, %s, 4326), 3857)"#,
+            cost = self.cost(),
+            reverse_cost = self.reverse_cost()
+        )
+    }
 }
 
 #[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
@@ -29,6 +180,9 @@ impl Edge {
     pub async fn route(
         start_node: &Node,
         end_node: &Node,
+        kind: RouteKind,
+        profile: &RoutingProfile,
+        departure: &DateTime<Local>,
         conn: &sqlx::Pool<Postgres>,
     ) -> Vec<Point> {
         let biggest_lng = start_node.lng.max(end_node.lng) + 0.16;
@@ -36,22 +190,22 @@ impl Edge {
         let smallest_lng = start_node.lng.min(end_node.lng) - 0.16;
         let smallest_lat = start_node.lat.min(end_node.lat) - 0.16;
 
-        let request = r#"SELECT distinct on (pa.path_seq)
+        let request = format!(
+            r#"SELECT distinct on (pa.path_seq)
                                     e.x1 as x,
                                     e.y1 as y,
                                     way_id,
                                     st_length(st_transform(geom ,4326)::geography) as length,
-                                    node as node_id
+                                    node as node_id,
+                                    e.score,
+                                    e.cost_road <= 1 / 0.9 as cycleway,
+                                    e.z1 as elevation
                                         FROM pgr_astar(
                                             FORMAT(
                                                 $FORMAT$
-                                                SELECT *
-                                                from edge
-                                                where target is not null
-                                                and cost is not null
-                                                and geom && ST_Transform(ST_MakeEnvelope(%s, %s, %s, %s, 4326), 3857)            
+                                                {edges}
                                                 $FORMAT$,
-                                                $3, $4, $5, $6
+                                                $7, $8, $3, $4, $5, $6
                                             )
                                         , 
                                         $1, 
@@ -60,15 +214,19 @@ impl Edge {
                                         epsilon => 1
                                         ) as pa
                                     join edge e on pa.edge = e.id 
-                                    ORDER BY pa.path_seq ASC"#;
+                                    ORDER BY pa.path_seq ASC"#,
+            edges = kind.edges()
+        );
 
-        let response: Vec<Point> = match sqlx::query_as(request)
+        let response: Vec<Point> = match sqlx::query_as(&request)
             .bind(start_node.node_id)
             .bind(end_node.node_id)
             .bind(biggest_lng)
             .bind(biggest_lat)
             .bind(smallest_lng)
             .bind(smallest_lat)
+            .bind(profile.id)
+            .bind(departure)
             .fetch_all(conn)
             .await
         {
@@ -81,6 +239,47 @@ impl Edge {
         response
     }
 
+    // The area reachable from the node with a weighted cost below the budget,
+    // as a GeoJSON polygon.
+    pub async fn isochrone(
+        start_node: &Node,
+        budget: f64,
+        profile: &RoutingProfile,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<Option<String>, sqlx::Error> {
+        // the cost is at least the length in the 3857 projection
+        let padding = budget / 111_320.0;
+        let request = format!(
+            r#"SELECT ST_AsGeoJSON(ST_Transform(ST_ConcaveHull(ST_Collect(e.geom), 0.8), 4326))
+                    FROM pgr_drivingDistance(
+                        FORMAT(
+                            $FORMAT$
+                            {edges}
+                            $FORMAT$,
+                            $3, $8, $4, $5, $6, $7
+                        ),
+                        $1,
+                        $2,
+                        directed => true
+                    ) as dd
+                    join edge e on dd.edge = e.id"#,
+            edges = RouteKind::Safest.edges()
+        );
+        let geojson: Option<String> = sqlx::query(&request)
+            .bind(start_node.node_id)
+            .bind(budget)
+            .bind(profile.id)
+            .bind(start_node.lng + padding)
+            .bind(start_node.lat + padding)
+            .bind(start_node.lng - padding)
+            .bind(start_node.lat - padding)
+            .bind(Local::now())
+            .fetch_one(conn)
+            .await?
+            .get(0);
+        Ok(geojson)
+    }
+
     pub async fn route_without_score(
         start_node: &Node,
         end_node: &Node,
@@ -96,7 +295,10 @@ impl Edge {
                                     e.y1 as y,
                                     way_id,
                                     st_length(st_transform(geom ,4326)::geography) as length,
-                                    node as node_id
+                                    node as node_id,
+                                    e.score,
+                                    e.cost_road <= 1 / 0.9 as cycleway,
+                                    e.z1 as elevation
                                         FROM pgr_bdastar(
                                             FORMAT(
                                                 $FORMAT$
@@ -188,3 +390,27 @@ impl Edge {
         Ok(response.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RouteKind;
+
+    #[test]
+    fn shortest_keeps_the_closures_and_the_oneways() {
+        // a closed way
+        assert_eq!(
+            RouteKind::Shortest.costs(1000.0, 1000.0, 1.0, 10.0, Some(0.0), false),
+            (1000.0, 1000.0)
+        );
+        // a oneway
+        assert_eq!(
+            RouteKind::Shortest.costs(12.0, 1000.0, 1.2, 10.0, None, true),
+            (10.0, 1000.0)
+        );
+        // a two way street climbed in reverse
+        assert_eq!(
+            RouteKind::Shortest.costs(12.0, 15.0, 1.2, 10.0, Some(0.5), false),
+            (10.0, 10.0)
+        );
+    }
+}
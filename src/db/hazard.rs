@@ -0,0 +1,109 @@
+use chrono::{DateTime, Local};
+use sqlx::Postgres;
+use uuid::Uuid;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Hazard {
+    pub issue: String,
+    pub comment: Option<String>,
+    pub lng: f64,
+    pub lat: f64,
+    pub created_at: DateTime<Local>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ModeratedHazard {
+    pub id: i32,
+    pub issue: String,
+    pub comment: Option<String>,
+    pub hidden: bool,
+    pub created_at: DateTime<Local>,
+}
+
+impl Hazard {
+    pub async fn get_recents(
+        lng1: f64,
+        lat1: f64,
+        lng2: f64,
+        lat2: f64,
+        issue: Option<&str>,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<Vec<Hazard>, sqlx::Error> {
+        sqlx::query_as(
+            r#"select issue,
+                      comment,
+                      st_x(st_transform(geom, 4326)) as lng,
+                      st_y(st_transform(geom, 4326)) as lat,
+                      created_at
+               from hazard
+               where geom && ST_Transform(st_makeenvelope($1, $2, $3, $4, 4326), 3857)
+               and not hidden
+               and ($5::text is null or issue = $5)
+               order by created_at desc
+               limit 100"#,
+        )
+        .bind(lng1)
+        .bind(lat1)
+        .bind(lng2)
+        .bind(lat2)
+        .bind(issue)
+        .fetch_all(conn)
+        .await
+    }
+
+    pub async fn insert(
+        lng: f64,
+        lat: f64,
+        issue: &str,
+        comment: &Option<String>,
+        user_id: &Uuid,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<i32, sqlx::Error> {
+        let (id,): (i32,) = sqlx::query_as(
+            r#"insert into hazard (geom, issue, comment, user_id)
+               values (st_transform(st_setsrid(st_makepoint($1, $2), 4326), 3857), $3, $4, $5)
+               returning id"#,
+        )
+        .bind(lng)
+        .bind(lat)
+        .bind(issue)
+        .bind(comment)
+        .bind(user_id)
+        .fetch_one(conn)
+        .await?;
+        // the edges near the hazard cost more at the next refresh of the edge view,
+        // an anonymous post doesn't rebuild the graph
+        Ok(id)
+    }
+
+    // the last hazards, hidden or not
+    pub async fn get_moderation(
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<Vec<ModeratedHazard>, sqlx::Error> {
+        sqlx::query_as(
+            r#"select id, issue, comment, hidden, created_at
+               from hazard
+               order by created_at desc
+               limit 100"#,
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    pub async fn set_hidden(
+        id: i32,
+        hidden: bool,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"update hazard
+               set hidden = $2
+               where id = $1"#,
+        )
+        .bind(id)
+        .bind(hidden)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
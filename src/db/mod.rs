@@ -1,5 +1,12 @@
 pub mod cyclability_score;
 pub mod cycleway;
 pub mod edge;
+pub mod hazard;
+pub mod routing_profile;
+pub mod score_report;
+pub mod score_vote;
 pub mod search_db;
+pub mod shared_route;
+pub mod subscription;
 pub mod user;
+pub mod webhook;
@@ -0,0 +1,49 @@
+use serde::Serialize;
+use sqlx::Postgres;
+use utoipa::ToSchema;
+
+#[derive(Debug, sqlx::FromRow, Clone, Serialize, ToSchema)]
+pub struct RoutingProfile {
+    pub id: i32,
+    pub name: String,
+    pub label: String,
+    pub score_penalty: f64,
+    pub contraflow_factor: Option<f64>,
+    // multiplier of the edges near a hazard, None ignores the hazards
+    pub hazard_factor: Option<f64>,
+}
+
+impl RoutingProfile {
+    pub async fn get_all(conn: &sqlx::Pool<Postgres>) -> Vec<RoutingProfile> {
+        match sqlx::query_as(
+            r#"select id, name, label, score_penalty, contraflow_factor, hazard_factor
+               from routing_profile
+               order by id"#,
+        )
+        .fetch_all(conn)
+        .await
+        {
+            Ok(profiles) => profiles,
+            Err(e) => {
+                eprintln!("Error while fetching routing profiles: {}", e);
+                vec![]
+            }
+        }
+    }
+
+    // Falls back on the first profile when the name is unknown
+    pub async fn get_by_name(
+        name: &str,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<RoutingProfile, sqlx::Error> {
+        sqlx::query_as(
+            r#"select id, name, label, score_penalty, contraflow_factor, hazard_factor
+               from routing_profile
+               order by name = $1 desc, id
+               limit 1"#,
+        )
+        .bind(name)
+        .fetch_one(conn)
+        .await
+    }
+}
@@ -0,0 +1,86 @@
+use sqlx::Postgres;
+use std::collections::HashMap;
+
+// Reports not yet reviewed, from different riders, before a contribution is hidden
+pub const REPORT_THRESHOLD: i64 = 3;
+
+pub struct ScoreReport;
+
+impl ScoreReport {
+    // A rider reports a contribution once, the reporter is the keycloak sub.
+    // Returns true if the contribution just crossed the threshold.
+    pub async fn insert(
+        score_id: i32,
+        reason: &str,
+        reporter: &str,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query(
+            r#"insert into score_report (cyclability_score_id, reason, reporter)
+               values ($1, $2, $3)
+               on conflict (cyclability_score_id, reporter) do nothing"#,
+        )
+        .bind(score_id)
+        .bind(reason)
+        .bind(reporter)
+        .execute(conn)
+        .await?;
+
+        let (count,): (i64,) = sqlx::query_as(
+            r#"select count(*)
+               from score_report
+               where cyclability_score_id = $1
+               and not reviewed"#,
+        )
+        .bind(score_id)
+        .fetch_one(conn)
+        .await?;
+
+        Ok(count == REPORT_THRESHOLD)
+    }
+
+    // The reasons of the reports not yet reviewed, by contribution
+    pub async fn get_reasons(
+        score_ids: &[i32],
+        conn: &sqlx::Pool<Postgres>,
+    ) -> HashMap<i32, Vec<String>> {
+        let reports: Vec<(i32, String)> = match sqlx::query_as(
+            r#"select cyclability_score_id, reason
+               from score_report
+               where cyclability_score_id = any($1)
+               and not reviewed
+               order by created_at"#,
+        )
+        .bind(score_ids)
+        .fetch_all(conn)
+        .await
+        {
+            Ok(reports) => reports,
+            Err(e) => {
+                eprintln!("Error while fetching the reports: {}", e);
+                vec![]
+            }
+        };
+        reports
+            .into_iter()
+            .fold(HashMap::new(), |mut reasons, (score_id, reason)| {
+                reasons
+                    .entry(score_id)
+                    .or_insert_with(Vec::new)
+                    .push(reason);
+                reasons
+            })
+    }
+
+    pub async fn review(score_id: i32, conn: &sqlx::Pool<Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"update score_report
+               set reviewed = true
+               where cyclability_score_id = $1"#,
+        )
+        .bind(score_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use sqlx::Postgres;
+use std::collections::HashMap;
+
+pub struct ScoreVote;
+
+impl ScoreVote {
+    // A rider can change their vote. A still accurate vote refreshes the score.
+    pub async fn vote(
+        score_id: i32,
+        voter: &str,
+        still_valid: bool,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"insert into score_vote (cyclability_score_id, voter, still_valid)
+               values ($1, $2, $3)
+               on conflict (cyclability_score_id, voter)
+               do update set still_valid = $3, created_at = now()"#,
+        )
+        .bind(score_id)
+        .bind(voter)
+        .bind(still_valid)
+        .execute(conn)
+        .await?;
+
+        if still_valid {
+            sqlx::query(
+                r#"update cyclability_score
+                   set confirmed_at = now()
+                   where id = $1"#,
+            )
+            .bind(score_id)
+            .execute(conn)
+            .await?;
+        }
+        Ok(())
+    }
+
+    // still accurate, no longer accurate
+    pub async fn get_counts(score_id: i32, conn: &sqlx::Pool<Postgres>) -> (i64, i64) {
+        match sqlx::query_as(
+            r#"select count(*) filter (where still_valid),
+                      count(*) filter (where not still_valid)
+               from score_vote
+               where cyclability_score_id = $1"#,
+        )
+        .bind(score_id)
+        .fetch_one(conn)
+        .await
+        {
+            Ok(counts) => counts,
+            Err(e) => {
+                eprintln!("Error while counting the votes: {}", e);
+                (0, 0)
+            }
+        }
+    }
+
+    // still accurate, no longer accurate of many scores in one query
+    pub async fn get_counts_by_ids(
+        score_ids: &[i32],
+        conn: &sqlx::Pool<Postgres>,
+    ) -> HashMap<i32, (i64, i64)> {
+        let counts: Vec<(i32, i64, i64)> = match sqlx::query_as(
+            r#"select cyclability_score_id,
+                      count(*) filter (where still_valid),
+                      count(*) filter (where not still_valid)
+               from score_vote
+               where cyclability_score_id = any($1)
+               group by cyclability_score_id"#,
+        )
+        .bind(score_ids)
+        .fetch_all(conn)
+        .await
+        {
+            Ok(counts) => counts,
+            Err(e) => {
+                eprintln!("Error while counting the votes: {}", e);
+                vec![]
+            }
+        };
+        counts
+            .into_iter()
+            .map(|(score_id, still_valid, not_valid)| (score_id, (still_valid, not_valid)))
+            .collect()
+    }
+}
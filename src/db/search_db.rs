@@ -1,6 +1,9 @@
+use serde::Serialize;
 use sqlx::Postgres;
+use utoipa::ToSchema;
 
-#[derive(sqlx::FromRow, Debug)]
+#[derive(sqlx::FromRow, Debug, Serialize, ToSchema)]
+#[schema(as = SearchResult)]
 pub struct SearchResultDB {
     pub name: String,
     pub lng: f64,
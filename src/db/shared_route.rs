@@ -0,0 +1,54 @@
+use chrono::{DateTime, Local};
+use sqlx::Postgres;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct SharedRoute {
+    pub waypoints: String,
+    pub kind: String,
+    pub profile: String,
+    pub node_ids: Vec<i64>,
+    pub created_at: DateTime<Local>,
+}
+
+impl SharedRoute {
+    pub async fn get(id: &str, conn: &sqlx::Pool<Postgres>) -> Result<SharedRoute, sqlx::Error> {
+        sqlx::query_as(
+            r#"select waypoints, kind, profile, node_ids, created_at
+               from shared_route
+               where id = $1"#,
+        )
+        .bind(id)
+        .fetch_one(conn)
+        .await
+    }
+
+    // The geometry is a "lng,lat" list in 4326
+    pub async fn insert(
+        id: &str,
+        waypoints: &str,
+        kind: &str,
+        profile: &str,
+        node_ids: &[i64],
+        coordinates: &[(f64, f64)],
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<(), sqlx::Error> {
+        let linestring = coordinates
+            .iter()
+            .map(|(lng, lat)| format!("{lng} {lat}"))
+            .collect::<Vec<String>>()
+            .join(",");
+        sqlx::query(
+            r#"insert into shared_route (id, waypoints, kind, profile, node_ids, geom)
+               values ($1, $2, $3, $4, $5, ST_Transform(ST_GeomFromText('LINESTRING(' || $6 || ')', 4326), 3857))"#,
+        )
+        .bind(id)
+        .bind(waypoints)
+        .bind(kind)
+        .bind(profile)
+        .bind(node_ids)
+        .bind(linestring)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
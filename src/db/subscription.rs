@@ -0,0 +1,133 @@
+use chrono::{DateTime, Local};
+use sqlx::Postgres;
+use uuid::Uuid;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Subscription {
+    pub id: i32,
+    pub email: String,
+    pub token: Uuid,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct DigestScore {
+    pub id: i32,
+    pub name: Option<Vec<Option<String>>>,
+    pub score: f64,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Local>,
+}
+
+impl Subscription {
+    // either the way_ids or the area (lng1, lat1, lng2, lat2) is followed,
+    // the subscription is pending until confirmed
+    pub async fn insert(
+        email: &str,
+        way_ids: Option<&Vec<i64>>,
+        area: Option<(f64, f64, f64, f64)>,
+        user_id: &Uuid,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<Subscription, sqlx::Error> {
+        let (lng1, lat1, lng2, lat2) = match area {
+            Some((lng1, lat1, lng2, lat2)) => (Some(lng1), Some(lat1), Some(lng2), Some(lat2)),
+            None => (None, None, None, None),
+        };
+        sqlx::query_as(
+            r#"insert into subscription (email, way_ids, geom, user_id)
+               values ($1, $2,
+                       case when $3::float8 is null then null
+                       else ST_Transform(st_makeenvelope($3, $4, $5, $6, 4326), 3857) end,
+                       $7)
+               returning id, email, token"#,
+        )
+        .bind(email)
+        .bind(way_ids)
+        .bind(lng1)
+        .bind(lat1)
+        .bind(lng2)
+        .bind(lat2)
+        .bind(user_id)
+        .fetch_one(conn)
+        .await
+    }
+
+    pub async fn get_all(conn: &sqlx::Pool<Postgres>) -> Result<Vec<Subscription>, sqlx::Error> {
+        sqlx::query_as(
+            r#"select id, email, token
+               from subscription
+               where confirmed_at is not null
+               order by id"#,
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    // the visible scores given since the last digest on the followed ways or area
+    pub async fn get_new_scores(
+        &self,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<Vec<DigestScore>, sqlx::Error> {
+        sqlx::query_as(
+            r#"select cs.id, cs.name, cs.score, cs.comment, cs.created_at
+               from cyclability_score cs, subscription s
+               where s.id = $1
+               and not cs.hidden
+               and cs.created_at > s.last_sent_at
+               and (cs.way_ids && s.way_ids or st_intersects(cs.geom, s.geom))
+               order by cs.created_at"#,
+        )
+        .bind(self.id)
+        .fetch_all(conn)
+        .await
+    }
+
+    // the next digest starts after the last score sent
+    pub async fn set_sent(
+        &self,
+        last_created_at: &DateTime<Local>,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"update subscription
+               set last_sent_at = $2
+               where id = $1"#,
+        )
+        .bind(self.id)
+        .bind(last_created_at)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    // false when the token is unknown, the digest starts at the confirmation
+    pub async fn confirm_by_token(
+        token: &Uuid,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"update subscription
+               set confirmed_at = coalesce(confirmed_at, now()),
+                   last_sent_at = case when confirmed_at is null then now() else last_sent_at end
+               where token = $1"#,
+        )
+        .bind(token)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // false when the token is unknown
+    pub async fn delete_by_token(
+        token: &Uuid,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"delete from subscription
+               where token = $1"#,
+        )
+        .bind(token)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
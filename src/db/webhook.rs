@@ -0,0 +1,155 @@
+use chrono::{DateTime, Local};
+use sqlx::Postgres;
+
+// After the last attempt the delivery is left undelivered with its last error
+pub const MAX_ATTEMPTS: i32 = 10;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    pub categories: Option<Vec<String>>,
+    // lng1, lat1, lng2, lat2 of the filter
+    pub bbox: Option<String>,
+    pub created_at: DateTime<Local>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub url: String,
+    pub secret: String,
+    pub payload: serde_json::Value,
+}
+
+impl Webhook {
+    pub async fn get_all(conn: &sqlx::Pool<Postgres>) -> Result<Vec<Webhook>, sqlx::Error> {
+        sqlx::query_as(
+            r#"select id,
+                      url,
+                      categories,
+                      case when geom is null then null
+                      else concat_ws(',', st_xmin(area.box), st_ymin(area.box), st_xmax(area.box), st_ymax(area.box)) end as bbox,
+                      created_at
+               from webhook,
+               lateral (select st_transform(geom, 4326)::box2d as box) as area
+               order by id"#,
+        )
+        .fetch_all(conn)
+        .await
+    }
+
+    pub async fn insert(
+        url: &str,
+        secret: &str,
+        categories: Option<&Vec<String>>,
+        area: Option<(f64, f64, f64, f64)>,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<i32, sqlx::Error> {
+        let (lng1, lat1, lng2, lat2) = match area {
+            Some((lng1, lat1, lng2, lat2)) => (Some(lng1), Some(lat1), Some(lng2), Some(lat2)),
+            None => (None, None, None, None),
+        };
+        let (id,): (i32,) = sqlx::query_as(
+            r#"insert into webhook (url, secret, categories, geom)
+               values ($1, $2, $3,
+                       case when $4::float8 is null then null
+                       else ST_Transform(st_makeenvelope($4, $5, $6, $7, 4326), 3857) end)
+               returning id"#,
+        )
+        .bind(url)
+        .bind(secret)
+        .bind(categories)
+        .bind(lng1)
+        .bind(lat1)
+        .bind(lng2)
+        .bind(lat2)
+        .fetch_one(conn)
+        .await?;
+        Ok(id)
+    }
+
+    pub async fn delete(id: i32, conn: &sqlx::Pool<Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"delete from webhook
+               where id = $1"#,
+        )
+        .bind(id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+impl WebhookDelivery {
+    // one delivery per webhook whose filters match the score
+    pub async fn enqueue(
+        score_id: i32,
+        category: &str,
+        payload: &serde_json::Value,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"insert into webhook_delivery (webhook_id, cyclability_score_id, payload)
+               select w.id, cs.id, $3
+               from webhook w, cyclability_score cs
+               where cs.id = $1
+               and (w.categories is null or $2 = any(w.categories))
+               and (w.geom is null or st_intersects(w.geom, cs.geom))"#,
+        )
+        .bind(score_id)
+        .bind(category)
+        .bind(payload)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn get_due(conn: &sqlx::Pool<Postgres>) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        sqlx::query_as(
+            r#"select d.id, w.url, w.secret, d.payload
+               from webhook_delivery d
+               join webhook w on w.id = d.webhook_id
+               where d.delivered_at is null
+               and d.attempts < $1
+               and d.next_attempt_at <= now()
+               order by d.next_attempt_at
+               limit 100"#,
+        )
+        .bind(MAX_ATTEMPTS)
+        .fetch_all(conn)
+        .await
+    }
+
+    pub async fn set_delivered(&self, conn: &sqlx::Pool<Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"update webhook_delivery
+               set delivered_at = now(), attempts = attempts + 1, last_error = null
+               where id = $1"#,
+        )
+        .bind(self.id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    // the next attempt waits twice as long, from 1 minute up to about 4 hours
+    pub async fn set_failed(
+        &self,
+        error: &str,
+        conn: &sqlx::Pool<Postgres>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"update webhook_delivery
+               set attempts = attempts + 1,
+                   last_error = $2,
+                   next_attempt_at = now() + make_interval(mins => power(2, attempts)::int)
+               where id = $1"#,
+        )
+        .bind(self.id)
+        .bind(error)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use crate::component::score_selector::Category;
+use crate::db::subscription::Subscription;
+use askama::Template;
+use lazy_static::lazy_static;
+use lettre::message::header::{ContentType, HeaderName, HeaderValue};
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use sqlx::PgPool;
+use std::env;
+
+lazy_static! {
+    static ref VELOINFO_URL: String = env::var("VELOINFO_URL").expect("VELOINFO_URL must be set");
+}
+
+#[derive(Template)]
+#[template(path = "digest.txt")]
+struct DigestMail {
+    scores: Vec<DigestLine>,
+    unsubscribe_url: String,
+}
+
+struct DigestLine {
+    name: String,
+    category: &'static str,
+    comment: String,
+    date: String,
+    url: String,
+}
+
+#[derive(Template)]
+#[template(path = "subscription_confirmation.txt")]
+struct ConfirmationMail {
+    confirm_url: String,
+}
+
+// No mail is sent without SMTP_URL, ex: smtp://mailpit:1025
+fn mailer() -> Option<(AsyncSmtpTransport<Tokio1Executor>, Mailbox)> {
+    let Ok(smtp_url) = env::var("SMTP_URL") else {
+        println!("SMTP_URL is not set, no mail sent");
+        return None;
+    };
+    let from = env::var("SMTP_FROM").unwrap_or("veloinfo@veloinfo.ca".to_string());
+    let from: Mailbox = match from.parse() {
+        Ok(from) => from,
+        Err(e) => {
+            eprintln!("Invalid SMTP_FROM {}: {}", from, e);
+            return None;
+        }
+    };
+    match AsyncSmtpTransport::<Tokio1Executor>::from_url(&smtp_url) {
+        Ok(mailer) => Some((mailer.build(), from)),
+        Err(e) => {
+            eprintln!("Invalid SMTP_URL: {}", e);
+            None
+        }
+    }
+}
+
+// The link to confirm a pending subscription, false when it is not sent
+pub async fn send_confirmation(subscription: &Subscription) -> bool {
+    let Some((mailer, from)) = mailer() else {
+        return false;
+    };
+    let to = match subscription.email.parse() {
+        Ok(to) => to,
+        Err(e) => {
+            eprintln!("Invalid email {}: {}", subscription.email, e);
+            return false;
+        }
+    };
+    let body = ConfirmationMail {
+        confirm_url: format!(
+            "{}/subscription/confirm/{}",
+            VELOINFO_URL.as_str(),
+            subscription.token
+        ),
+    };
+    let message = Message::builder()
+        .from(from)
+        .to(to)
+        .subject("Veloinfo : confirmez votre abonnement")
+        .header(ContentType::TEXT_PLAIN)
+        .body(body.render().unwrap());
+    let message = match message {
+        Ok(message) => message,
+        Err(e) => {
+            eprintln!("Error while building the confirmation: {}", e);
+            return false;
+        }
+    };
+    match mailer.send(message).await {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!(
+                "Error while sending the confirmation to {}: {}",
+                subscription.email, e
+            );
+            false
+        }
+    }
+}
+
+// Sends the new contributions on the followed ways and areas
+// of the confirmed subscriptions.
+pub async fn send(conn: &PgPool) {
+    let Some((mailer, from)) = mailer() else {
+        return;
+    };
+
+    let subscriptions = match Subscription::get_all(conn).await {
+        Ok(subscriptions) => subscriptions,
+        Err(e) => {
+            eprintln!("Error while getting the subscriptions: {}", e);
+            return;
+        }
+    };
+    for subscription in subscriptions {
+        let scores = match subscription.get_new_scores(conn).await {
+            Ok(scores) => scores,
+            Err(e) => {
+                eprintln!(
+                    "Error while getting the scores of the subscription {}: {}",
+                    subscription.id, e
+                );
+                continue;
+            }
+        };
+        let Some(last_created_at) = scores.last().map(|score| score.created_at) else {
+            continue;
+        };
+        let unsubscribe_url = format!(
+            "{}/unsubscribe/{}",
+            VELOINFO_URL.as_str(),
+            subscription.token
+        );
+        let body = DigestMail {
+            scores: scores
+                .into_iter()
+                .map(|score| DigestLine {
+                    name: score
+                        .name
+                        .unwrap_or_default()
+                        .into_iter()
+                        .flatten()
+                        .next()
+                        .unwrap_or("Sans nom".to_string()),
+                    category: Category::from_score(score.score).label(),
+                    comment: score.comment.unwrap_or_default(),
+                    date: score.created_at.format("%Y-%m-%d %H:%M").to_string(),
+                    url: format!("{}/?score={}", VELOINFO_URL.as_str(), score.id),
+                })
+                .collect(),
+            unsubscribe_url: unsubscribe_url.clone(),
+        };
+        let to = match subscription.email.parse() {
+            Ok(to) => to,
+            Err(e) => {
+                eprintln!("Invalid email {}: {}", subscription.email, e);
+                continue;
+            }
+        };
+        let message = Message::builder()
+            .from(from.clone())
+            .to(to)
+            .subject("Veloinfo : nouvelles contributions")
+            .header(ContentType::TEXT_PLAIN)
+            // the one click unsubscribe of the mail clients, RFC 8058
+            .raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str("List-Unsubscribe"),
+                format!("<{}>", unsubscribe_url),
+            ))
+            .raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+                "List-Unsubscribe=One-Click".to_string(),
+            ))
+            .body(body.render().unwrap());
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("Error while building the digest: {}", e);
+                continue;
+            }
+        };
+        // not marked as sent, it is tried again the next day
+        if let Err(e) = mailer.send(message).await {
+            eprintln!(
+                "Error while sending the digest to {}: {}",
+                subscription.email, e
+            );
+            continue;
+        }
+        if let Err(e) = subscription.set_sent(&last_created_at, conn).await {
+            eprintln!(
+                "Error while updating the subscription {}: {}",
+                subscription.id, e
+            );
+        }
+    }
+}
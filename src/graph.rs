@@ -0,0 +1,399 @@
+use crate::db::edge::{Point, RouteKind};
+use crate::db::routing_profile::RoutingProfile;
+use chrono::{DateTime, Datelike, Local};
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use sqlx::Postgres;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+lazy_static! {
+    static ref GRAPH: RwLock<Option<Arc<Graph>>> = RwLock::new(None);
+    // only one load at a time so an older graph never replaces a newer one
+    static ref LOADING: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+// The graph used for routing, None until the first load is done.
+pub fn get() -> Option<Arc<Graph>> {
+    match GRAPH.read() {
+        Ok(graph) => graph.clone(),
+        Err(e) => {
+            eprintln!("Error while reading the routing graph: {}", e);
+            None
+        }
+    }
+}
+
+// Loads the `edge` view in memory. Must be called again every time the view
+// is refreshed, the routing falls back on pgRouting until then.
+pub async fn load(conn: &sqlx::Pool<Postgres>) {
+    let _loading = LOADING.lock().await;
+    let start = Instant::now();
+    match Graph::load(conn).await {
+        Ok(graph) => {
+            println!(
+                "Routing graph loaded: {} nodes, {} edges in {:?}",
+                graph.node_ids.len(),
+                graph.edges.len(),
+                start.elapsed()
+            );
+            match GRAPH.write() {
+                Ok(mut current) => *current = Some(Arc::new(graph)),
+                Err(e) => eprintln!("Error while replacing the routing graph: {}", e),
+            }
+        }
+        Err(e) => eprintln!("Error while loading the routing graph: {}", e),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct GraphEdgeDb {
+    source: i64,
+    target: i64,
+    x1: f64,
+    y1: f64,
+    x2: f64,
+    y2: f64,
+    way_id: i64,
+    length: f64,
+    length_m: f64,
+    cost: f64,
+    reverse_cost: f64,
+    cost_road: f64,
+    score: Option<f64>,
+    decayed_score: Option<f64>,
+    z1: Option<f64>,
+    z2: Option<f64>,
+    closed_from: Option<DateTime<Local>>,
+    closed_until: Option<DateTime<Local>>,
+    winter_factor: f64,
+    hazard: bool,
+    oneway: bool,
+    weights: Vec<f64>,
+}
+
+struct GraphEdge {
+    source: usize,
+    target: usize,
+    way_id: i64,
+    // in the 3857 projection like the costs
+    length: f64,
+    // meters
+    length_m: f64,
+    cost: f64,
+    reverse_cost: f64,
+    cost_road: f64,
+    score: Option<f64>,
+    // the score moving toward 1 as it gets old, for the costs
+    decayed_score: Option<f64>,
+    z1: Option<f64>,
+    z2: Option<f64>,
+    // the closure of a score 0 when it is limited in time
+    closed_from: Option<DateTime<Local>>,
+    closed_until: Option<DateTime<Local>>,
+    // applied from december to march
+    winter_factor: f64,
+    // near a hazard point
+    hazard: bool,
+    // from the tags, a climb also makes the reverse cost higher
+    oneway: bool,
+    // the routing_profile_weight of each profile, in the order of `profile_ids`
+    weights: Vec<f64>,
+}
+
+impl GraphEdge {
+    // Only the closures limited in time, the others are already in the cost
+    fn is_closed(&self, departure: &DateTime<Local>) -> bool {
+        self.score == Some(0.0)
+            && (self.closed_from.is_some() || self.closed_until.is_some())
+            && self.closed_from.is_none_or(|from| from <= *departure)
+            && self.closed_until.is_none_or(|until| until > *departure)
+    }
+}
+
+pub struct Graph {
+    node_ids: Vec<i64>,
+    index: HashMap<i64, usize>,
+    // lng, lat of each node
+    coordinates: Vec<(f64, f64)>,
+    edges: Vec<GraphEdge>,
+    // the edges leaving each node and if they are followed forward
+    adjacency: Vec<Vec<(usize, bool)>>,
+    profile_ids: Vec<i32>,
+    // the smallest weight of each profile, for the heuristic
+    min_weights: Vec<f64>,
+}
+
+impl Graph {
+    async fn load(conn: &sqlx::Pool<Postgres>) -> Result<Graph, sqlx::Error> {
+        let profile_ids: Vec<i32> =
+            sqlx::query_scalar(r#"select id from routing_profile order by id"#)
+                .fetch_all(conn)
+                .await?;
+        let mut graph = Graph {
+            node_ids: vec![],
+            index: HashMap::new(),
+            coordinates: vec![],
+            edges: vec![],
+            adjacency: vec![],
+            min_weights: vec![1.0; profile_ids.len()],
+            profile_ids,
+        };
+        let mut rows = sqlx::query_as::<_, GraphEdgeDb>(
+            r#"select e.source,
+                      e.target,
+                      e.x1,
+                      e.y1,
+                      e.x2,
+                      e.y2,
+                      e.way_id,
+                      st_length(e.geom) as length,
+                      e.length_m,
+                      e.cost,
+                      e.reverse_cost,
+                      e.cost_road,
+                      e.score,
+                      e.decayed_score,
+                      e.z1,
+                      e.z2,
+                      e.closed_from,
+                      e.closed_until,
+                      e.winter_factor,
+                      e.hazard,
+                      e.oneway,
+                      array(
+                          select coalesce(exp(sum(ln(rpw.weight))), 1)
+                          from routing_profile p
+                          left join routing_profile_weight rpw on rpw.profile_id = p.id
+                              and e.tags->>rpw.tag = rpw.value
+                          group by p.id
+                          order by p.id
+                      ) as weights
+               from edge e
+               where e.target is not null
+               and e.cost is not null"#,
+        )
+        .fetch(conn);
+        while let Some(row) = rows.try_next().await? {
+            let source = graph.node_index(row.source, (row.x1, row.y1));
+            let target = graph.node_index(row.target, (row.x2, row.y2));
+            for (min_weight, weight) in graph.min_weights.iter_mut().zip(row.weights.iter()) {
+                *min_weight = min_weight.min(*weight);
+            }
+            let edge = graph.edges.len();
+            graph.adjacency[source].push((edge, true));
+            graph.adjacency[target].push((edge, false));
+            graph.edges.push(GraphEdge {
+                source,
+                target,
+                way_id: row.way_id,
+                length: row.length,
+                length_m: row.length_m,
+                cost: row.cost,
+                reverse_cost: row.reverse_cost,
+                cost_road: row.cost_road,
+                score: row.score,
+                decayed_score: row.decayed_score,
+                z1: row.z1,
+                z2: row.z2,
+                closed_from: row.closed_from,
+                closed_until: row.closed_until,
+                winter_factor: row.winter_factor,
+                hazard: row.hazard,
+                oneway: row.oneway,
+                weights: row.weights,
+            });
+        }
+        Ok(graph)
+    }
+
+    fn node_index(&mut self, node_id: i64, coordinate: (f64, f64)) -> usize {
+        if let Some(index) = self.index.get(&node_id) {
+            return *index;
+        }
+        let index = self.node_ids.len();
+        self.index.insert(node_id, index);
+        self.node_ids.push(node_id);
+        self.coordinates.push(coordinate);
+        self.adjacency.push(vec![]);
+        index
+    }
+
+    // The same costs as `RouteKind::edges` for the edge followed forward or backward
+    fn cost(&self, edge: &GraphEdge, forward: bool, kind: RouteKind, weighting: &Weighting) -> f64 {
+        let (cost, reverse_cost) = kind.costs(
+            edge.cost,
+            edge.reverse_cost,
+            edge.cost_road,
+            edge.length,
+            edge.score,
+            edge.oneway,
+        );
+        let weight = edge.weights.get(weighting.index).copied().unwrap_or(1.0);
+        let mut factor = match edge.score {
+            Some(score) if score > 0.0 => {
+                (1.0 / edge.decayed_score.unwrap_or(score)).powf(weighting.score_penalty)
+            }
+            _ => 1.0,
+        };
+        if edge.is_closed(&weighting.departure) {
+            factor /= 0.001;
+        }
+        if weighting.winter {
+            factor *= edge.winter_factor;
+        }
+        if let Some(hazard_factor) = weighting.hazard_factor.filter(|_| edge.hazard) {
+            factor *= hazard_factor;
+        }
+        if forward {
+            return cost * weight * factor;
+        }
+        match weighting.contraflow_factor {
+            Some(contraflow_factor) if edge.oneway => {
+                cost * weight * factor * contraflow_factor
+            }
+            _ => reverse_cost * weight * factor,
+        }
+    }
+
+    // A lower bound of the cost between two nodes. The costs are never below
+    // the 3857 length, itself never below the length on the ground, times the
+    // smallest weight of the profile.
+    fn heuristic(&self, from: usize, to: usize, weighting: &Weighting) -> f64 {
+        let (lng1, lat1) = self.coordinates[from];
+        let (lng2, lat2) = self.coordinates[to];
+        let dlat = (lat2 - lat1).to_radians();
+        let dlng = (lng2 - lng1).to_radians();
+        let a = (dlat / 2.0).sin().powi(2)
+            + lat1.to_radians().cos() * lat2.to_radians().cos() * (dlng / 2.0).sin().powi(2);
+        // a little under the earth radius to stay below the ellipsoid length
+        let distance = 2.0 * 6_335_000.0 * a.sqrt().asin();
+        distance * weighting.min_weight
+    }
+
+    // A* between two nodes of the edge view. None when a node or the profile
+    // is not in the graph, or when there is no path.
+    pub fn route(
+        &self,
+        start_node: i64,
+        end_node: i64,
+        kind: RouteKind,
+        profile: &RoutingProfile,
+        departure: &DateTime<Local>,
+    ) -> Option<Vec<Point>> {
+        let start = *self.index.get(&start_node)?;
+        let end = *self.index.get(&end_node)?;
+        let profile_index = self.profile_ids.iter().position(|id| *id == profile.id)?;
+        let weighting = Weighting {
+            index: profile_index,
+            score_penalty: profile.score_penalty,
+            contraflow_factor: profile.contraflow_factor,
+            hazard_factor: profile.hazard_factor,
+            min_weight: self.min_weights[profile_index]
+                * profile.contraflow_factor.unwrap_or(1.0).min(1.0)
+                * profile.hazard_factor.unwrap_or(1.0).min(1.0),
+            departure: *departure,
+            winter: [12, 1, 2, 3].contains(&departure.month()),
+        };
+
+        let mut costs: HashMap<usize, f64> = HashMap::new();
+        // the edge used to reach each node
+        let mut previous: HashMap<usize, (usize, bool)> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        costs.insert(start, 0.0);
+        queue.push(State {
+            estimate: self.heuristic(start, end, &weighting),
+            node: start,
+        });
+        while let Some(State { estimate, node }) = queue.pop() {
+            if node == end {
+                break;
+            }
+            let cost = costs[&node];
+            if estimate > cost + self.heuristic(node, end, &weighting) {
+                // already reached with a smaller cost
+                continue;
+            }
+            for (edge_index, forward) in self.adjacency[node].iter() {
+                let edge = &self.edges[*edge_index];
+                let next = if *forward { edge.target } else { edge.source };
+                let next_cost = cost + self.cost(edge, *forward, kind, &weighting);
+                if costs.get(&next).is_none_or(|other| next_cost < *other) {
+                    costs.insert(next, next_cost);
+                    previous.insert(next, (*edge_index, *forward));
+                    queue.push(State {
+                        estimate: next_cost + self.heuristic(next, end, &weighting),
+                        node: next,
+                    });
+                }
+            }
+        }
+        if start != end && !previous.contains_key(&end) {
+            return None;
+        }
+
+        let mut points = vec![];
+        let mut node = end;
+        while let Some((edge_index, forward)) = previous.get(&node) {
+            let edge = &self.edges[*edge_index];
+            let (from, elevation) = if *forward {
+                (edge.source, edge.z1)
+            } else {
+                (edge.target, edge.z2)
+            };
+            let (x, y) = self.coordinates[from];
+            points.push(Point {
+                x,
+                y,
+                way_id: edge.way_id,
+                node_id: self.node_ids[from],
+                length: edge.length_m,
+                score: edge.score,
+                cycleway: edge.cost_road <= 1.0 / 0.9,
+                elevation,
+            });
+            node = from;
+        }
+        points.reverse();
+        Some(points)
+    }
+}
+
+struct Weighting {
+    index: usize,
+    score_penalty: f64,
+    contraflow_factor: Option<f64>,
+    hazard_factor: Option<f64>,
+    min_weight: f64,
+    departure: DateTime<Local>,
+    // the network cleared of snow is preferred from december to march
+    winter: bool,
+}
+
+// The nodes to visit, the smallest estimate first
+struct State {
+    estimate: f64,
+    node: usize,
+}
+
+impl PartialEq for State {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for State {}
+
+impl PartialOrd for State {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for State {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
@@ -0,0 +1,212 @@
+use crate::db::edge::Point;
+use crate::node::route_segments;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Maneuver {
+    Depart,
+    Continue,
+    SlightLeft,
+    Left,
+    SharpLeft,
+    SlightRight,
+    Right,
+    SharpRight,
+    UTurn,
+    Arrive,
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Instruction {
+    pub maneuver: Maneuver,
+    pub name: Option<String>,
+    // distance in meters until the next instruction
+    pub length: f64,
+    // most of the distance is on a dedicated cycleway
+    pub cycleway: bool,
+    // direction in degrees at the start of the instruction
+    pub bearing: f64,
+    pub text: String,
+}
+
+// Consecutive ways with the same name are ridden as one street
+struct Street {
+    name: Option<String>,
+    length: f64,
+    cycleway_length: f64,
+    coordinates: Vec<[f64; 2]>,
+}
+
+pub fn instructions(points: &[Point], names: &HashMap<i64, String>) -> Vec<Instruction> {
+    let mut streets: Vec<Street> = vec![];
+    for segment in route_segments(points) {
+        // the start and the end of the route are not on a way
+        if segment.way_id == 0 {
+            continue;
+        }
+        let name = names.get(&segment.way_id).cloned();
+        let cycleway_length = if segment.cycleway {
+            segment.length
+        } else {
+            0.0
+        };
+        match streets.last_mut() {
+            Some(street) if name.is_some() && street.name == name => {
+                street.length += segment.length;
+                street.cycleway_length += cycleway_length;
+                street
+                    .coordinates
+                    .extend(segment.coordinates.into_iter().skip(1));
+            }
+            _ => streets.push(Street {
+                name,
+                length: segment.length,
+                cycleway_length,
+                coordinates: segment.coordinates,
+            }),
+        }
+    }
+
+    let mut instructions: Vec<Instruction> = vec![];
+    let mut previous: Option<&Street> = None;
+    for street in streets.iter() {
+        let start_bearing = first_bearing(&street.coordinates);
+        let maneuver = match previous {
+            None => Maneuver::Depart,
+            Some(previous) => maneuver(last_bearing(&previous.coordinates), start_bearing),
+        };
+        let cycleway = street.cycleway_length > street.length / 2.0;
+        // a straight line on an unnamed way just makes the previous instruction longer
+        if let (Maneuver::Continue, None, Some(last)) =
+            (maneuver, &street.name, instructions.last_mut())
+        {
+            last.length += street.length;
+            previous = Some(street);
+            continue;
+        }
+        instructions.push(Instruction {
+            maneuver,
+            name: street.name.clone(),
+            length: street.length,
+            cycleway,
+            bearing: start_bearing,
+            text: "".to_string(),
+        });
+        previous = Some(street);
+    }
+    if !instructions.is_empty() {
+        instructions.push(Instruction {
+            maneuver: Maneuver::Arrive,
+            name: None,
+            length: 0.0,
+            cycleway: false,
+            bearing: 0.0,
+            text: "".to_string(),
+        });
+    }
+    for instruction in instructions.iter_mut() {
+        instruction.text = text(instruction);
+    }
+    instructions
+}
+
+fn maneuver(from: f64, to: f64) -> Maneuver {
+    // positive to the right, negative to the left
+    let angle = (to - from + 540.0) % 360.0 - 180.0;
+    match angle {
+        a if a.abs() < 20.0 => Maneuver::Continue,
+        a if a.abs() > 160.0 => Maneuver::UTurn,
+        a if a >= 120.0 => Maneuver::SharpRight,
+        a if a >= 50.0 => Maneuver::Right,
+        a if a > 0.0 => Maneuver::SlightRight,
+        a if a <= -120.0 => Maneuver::SharpLeft,
+        a if a <= -50.0 => Maneuver::Left,
+        _ => Maneuver::SlightLeft,
+    }
+}
+
+fn text(instruction: &Instruction) -> String {
+    let name = &instruction.name;
+    let on_name = match name {
+        Some(name) => format!(" sur {}", name),
+        None => "".to_string(),
+    };
+    let action = match instruction.maneuver {
+        Maneuver::Depart => format!("Partir vers {}{}", cardinal(instruction.bearing), on_name),
+        Maneuver::Continue => match name {
+            Some(_) => format!("Continuer{}", on_name),
+            None => "Continuer tout droit".to_string(),
+        },
+        Maneuver::SlightLeft => format!("Prendre légèrement à gauche{}", on_name),
+        Maneuver::Left => format!("Tourner à gauche{}", on_name),
+        Maneuver::SharpLeft => format!("Tourner franchement à gauche{}", on_name),
+        Maneuver::SlightRight => format!("Prendre légèrement à droite{}", on_name),
+        Maneuver::Right => format!("Tourner à droite{}", on_name),
+        Maneuver::SharpRight => format!("Tourner franchement à droite{}", on_name),
+        Maneuver::UTurn => format!("Faire demi-tour{}", on_name),
+        Maneuver::Arrive => return "Arrivée à destination".to_string(),
+    };
+    let on_cycleway = if instruction.cycleway {
+        " sur la piste cyclable"
+    } else {
+        ""
+    };
+    format!(
+        "{}, {}{}",
+        action,
+        distance(instruction.length),
+        on_cycleway
+    )
+}
+
+fn distance(meters: f64) -> String {
+    if meters >= 1000.0 {
+        format!("{:.1} km", meters / 1000.0).replace('.', ",")
+    } else {
+        format!("{} m", (meters / 10.0).round() * 10.0)
+    }
+}
+
+fn cardinal(bearing: f64) -> &'static str {
+    let directions = [
+        "le nord",
+        "le nord-est",
+        "l'est",
+        "le sud-est",
+        "le sud",
+        "le sud-ouest",
+        "l'ouest",
+        "le nord-ouest",
+    ];
+    directions[(((bearing + 22.5) % 360.0) / 45.0) as usize % 8]
+}
+
+fn first_bearing(coordinates: &[[f64; 2]]) -> f64 {
+    match coordinates {
+        [first, second, ..] => bearing(first, second),
+        _ => 0.0,
+    }
+}
+
+fn last_bearing(coordinates: &[[f64; 2]]) -> f64 {
+    match coordinates {
+        [.., before_last, last] => bearing(before_last, last),
+        _ => 0.0,
+    }
+}
+
+// Same as calculateBearing in index.js
+fn bearing([lon1, lat1]: &[f64; 2], [lon2, lat2]: &[f64; 2]) -> f64 {
+    let (lon1, lat1, lon2, lat2) = (
+        lon1.to_radians(),
+        lat1.to_radians(),
+        lon2.to_radians(),
+        lat2.to_radians(),
+    );
+    let y = (lon2 - lon1).sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * (lon2 - lon1).cos();
+    (y.atan2(x).to_degrees() + 360.0) % 360.0
+}
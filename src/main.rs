@@ -1,11 +1,18 @@
 use crate::auth::auth;
 use crate::auth::logout;
+use crate::component::feed::feed;
 use crate::component::index_js::indexjs;
 use crate::component::info_panel::info_panel_down;
 use crate::component::info_panel::info_panel_up;
 use crate::component::menu::{menu_close, menu_open};
+use crate::component::moderation::{
+    moderation_hazard_hide, moderation_hazard_show, moderation_hide, moderation_panel,
+    moderation_show,
+};
 use crate::component::photo_scroll::photo_scroll;
-use crate::component::point_panel::point_panel_lng_lat;
+use crate::component::point_panel::{hazard_post, point_panel_lng_lat};
+use crate::component::report::{report_form, report_post};
+use crate::component::score_votes::score_vote;
 use crate::component::search;
 use crate::component::segment_panel::segment_panel_bigger;
 use crate::component::segment_panel::segment_panel_bigger_route;
@@ -13,22 +20,45 @@ use crate::component::segment_panel::segment_panel_edit;
 use crate::component::segment_panel::segment_panel_get;
 use crate::component::segment_panel::segment_panel_lng_lat;
 use crate::component::segment_panel::segment_panel_post;
+use crate::component::segment_panel::segment_panel_score_delete;
+use crate::component::segment_panel::segment_panel_score_edit;
+use crate::component::segment_panel::segment_panel_score_post;
 use crate::component::segment_panel::select_score_id;
+use crate::component::subscription::{
+    subscription_area, subscription_confirm, subscription_confirm_post, subscription_post,
+    subscription_ways, unsubscribe, unsubscribe_post,
+};
+use crate::component::webhook_panel::{webhook_delete, webhook_panel, webhook_post};
+use crate::node::isochrone;
 use crate::node::route;
+use crate::node::route_geojson;
+use crate::node::route_gpx;
+use crate::node::route_instructions_get;
+use crate::node::route_loop;
+use crate::node::route_loop_geojson;
+use crate::node::route_share;
+use crate::node::route_share_get;
+use crate::node::route_via;
+use crate::node::route_via_geojson;
+use crate::node::route_via_gpx;
+use crate::node::route_via_instructions_get;
+use crate::node::route_via_share;
 use crate::score_selector_controler::score_bounds_controler;
 use askama::Template;
 use axum::extract::DefaultBodyLimit;
+use axum::extract::FromRef;
 use axum::http::HeaderMap;
 use axum::http::HeaderValue;
 use axum::http::Request;
 use axum::routing::post;
 use axum::routing::{get, Router};
+use axum_extra::extract::cookie::Key;
 use component::style::style;
 use lazy_static::lazy_static;
 use score_selector_controler::score_selector_controler;
 use sqlx::PgPool;
 use std::env;
-use std::process::Command;
+use tokio::process::Command;
 use tokio_cron_scheduler::{Job, JobScheduler};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
@@ -36,11 +66,16 @@ use tower_livereload::LiveReloadLayer;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+mod api;
 mod auth;
 mod component;
 mod db;
+mod digest;
+mod graph;
+mod instruction;
 mod node;
 mod score_selector_controler;
+mod webhook;
 
 lazy_static! {
     static ref IMAGE_DIR: String = env::var("IMAGE_DIR").unwrap();
@@ -51,6 +86,13 @@ struct VeloinfoState {
     conn: PgPool,
 }
 
+// the key of the signed cookies
+impl FromRef<VeloinfoState> for Key {
+    fn from_ref(_state: &VeloinfoState) -> Key {
+        auth::COOKIE_KEY.clone()
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let dev = env::var("ENV").unwrap().as_str().contains("dev");
@@ -69,16 +111,44 @@ async fn main() {
 
     sqlx::migrate!().run(&conn).await.unwrap();
 
+    // the routes are computed by pgRouting until the graph is loaded
+    let graph_conn = conn.clone();
+    tokio::spawn(async move {
+        graph::load(&graph_conn).await;
+    });
+
+    tokio::spawn(webhook::worker(conn.clone()));
+
     println!("Starting cron scheduler");
     let sched = JobScheduler::new().await.unwrap();
+    let import_conn = conn.clone();
+    sched
+        .add(
+            Job::new_async("0 0 7 * * *", move |_uuid, _l| {
+                let conn = import_conn.clone();
+                Box::pin(async move {
+                    println!("Importing data");
+                    let output = Command::new("./import.sh")
+                        .output()
+                        .await
+                        .expect("failed to execute process");
+                    println!("status: {}", output.status);
+                    graph::load(&conn).await;
+                })
+            })
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+    // the digest of the followed ways and areas, after the import
     sched
         .add(
-            Job::new("0 0 7 * * *", |_uuid, _l| {
-                println!("Importing data");
-                let output = Command::new("./import.sh")
-                    .output()
-                    .expect("failed to execute process");
-                println!("status: {}", output.status);
+            Job::new_async("0 0 8 * * *", move |_uuid, _l| {
+                let conn = conn.clone();
+                Box::pin(async move {
+                    println!("Sending the digests");
+                    digest::send(&conn).await;
+                })
             })
             .unwrap(),
         )
@@ -92,7 +162,13 @@ async fn main() {
         .route("/logout", get(logout))
         .route("/info_panel/down", get(info_panel_down))
         .route("/info_panel/up/:lng1/:lat1/:lng2/:lat2", get(info_panel_up))
-        .route("/segment_panel/id/:id", get(select_score_id))
+        .route(
+            "/segment_panel/id/:id",
+            get(select_score_id)
+                .post(segment_panel_score_post)
+                .delete(segment_panel_score_delete),
+        )
+        .route("/segment_panel/id/:id/edit", get(segment_panel_score_edit))
         .route(
             "/segment_panel_lng_lat/:lng/:lat",
             get(segment_panel_lng_lat),
@@ -106,17 +182,75 @@ async fn main() {
             get(segment_panel_bigger_route),
         )
         .route("/point_panel_lng_lat/:lng/:lat", get(point_panel_lng_lat))
+        .route("/hazard", post(hazard_post))
         .route("/search", post(search::post))
         .route("/search/open", get(search::open))
         .route("/menu/open/:lng/:lat/:zoom", get(menu_open))
         .route("/menu/closed", get(menu_close))
+        .route("/moderation", get(moderation_panel))
+        .route("/moderation/hide/:id", post(moderation_hide))
+        .route("/moderation/show/:id", post(moderation_show))
+        .route("/moderation/hazard/hide/:id", post(moderation_hazard_hide))
+        .route("/moderation/hazard/show/:id", post(moderation_hazard_show))
+        .route("/webhooks", get(webhook_panel).post(webhook_post))
+        .route("/webhooks/delete/:id", post(webhook_delete))
+        .route("/report/:id", get(report_form).post(report_post))
+        .route("/vote/:id/:still_valid", post(score_vote))
+        .route("/subscription", post(subscription_post))
+        .route("/subscription/ways/:way_ids", get(subscription_ways))
+        .route(
+            "/subscription/area/:lng1/:lat1/:lng2/:lat2",
+            get(subscription_area),
+        )
+        .route(
+            "/subscription/confirm/:token",
+            get(subscription_confirm).post(subscription_confirm_post),
+        )
+        .route(
+            "/unsubscribe/:token",
+            get(unsubscribe).post(unsubscribe_post),
+        )
+        .route("/feed.atom", get(feed))
         .route("/route/:start_lng/:start_lat/:end_lgt/:end_lat", get(route))
+        .route(
+            "/route/:start_lng/:start_lat/:end_lgt/:end_lat/gpx",
+            get(route_gpx),
+        )
+        .route(
+            "/route/:start_lng/:start_lat/:end_lgt/:end_lat/geojson",
+            get(route_geojson),
+        )
+        .route(
+            "/route/:start_lng/:start_lat/:end_lgt/:end_lat/instructions",
+            get(route_instructions_get),
+        )
+        .route(
+            "/route/:start_lng/:start_lat/:end_lgt/:end_lat/share",
+            post(route_share),
+        )
+        .route("/route/via/:waypoints", get(route_via))
+        .route("/route/loop/:lng/:lat/:distance", get(route_loop))
+        .route(
+            "/route/loop/:lng/:lat/:distance/geojson",
+            get(route_loop_geojson),
+        )
+        .route("/route/via/:waypoints/gpx", get(route_via_gpx))
+        .route("/route/via/:waypoints/geojson", get(route_via_geojson))
+        .route(
+            "/route/via/:waypoints/instructions",
+            get(route_via_instructions_get),
+        )
+        .route("/route/via/:waypoints/share", post(route_via_share))
+        .route("/route/share/:id", get(route_share_get))
+        .route("/isochrone/:lng/:lat/:minutes", get(isochrone))
         .route(
             "/cyclability_score/geom/:cyclability_score_id",
             get(score_bounds_controler),
         )
         .route("/score_selector/:score", get(score_selector_controler))
         .route("/photo_scroll/:photo/:way_ids", get(photo_scroll))
+        .nest("/api/v1", api::v1())
+        .route("/api/openapi.json", get(api::openapi))
         .route("/style.json", get(style))
         .route("/index.js", get(indexjs))
         .nest_service("/pub/", ServeDir::new("pub"))
@@ -1,40 +1,915 @@
-use crate::component::route_panel::RoutePanel;
-use crate::db::edge::{Edge, Point};
+use crate::component::route_gpx::RouteGpx;
+use crate::component::route_panel::{
+    CategoryLength, ReportedSegment, RouteAlternative, RoutePanel, RouteShare,
+};
+use crate::component::score_selector::Category;
+use crate::db::cyclability_score::CyclabilityScore;
+use crate::db::cycleway::Cycleway;
+use crate::db::edge::{Edge, Point, RouteKind};
+use crate::db::routing_profile::RoutingProfile;
+use crate::db::shared_route::SharedRoute;
+use crate::graph;
+use crate::instruction::{instructions, Instruction};
 use crate::VeloinfoState;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Json;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// average speed of a comfortable ride in km/h
+const RIDE_SPEED: f64 = 15.0;
+// meters climbed in an hour on top of the distance
+const CLIMB_RATE: f64 = 500.0;
+// a longer isochrone scans too much of the edges
+const MAX_ISOCHRONE_MINUTES: f64 = 120.0;
+// in meters, a longer loop is routed across the whole graph
+const MAX_LOOP_DISTANCE: f64 = 200_000.0;
+
+pub struct Route {
+    pub points: Vec<Point>,
+    // length of each leg in meters
+    pub legs: Vec<f64>,
+}
+
+impl Route {
+    pub fn node_ids(&self) -> Vec<i64> {
+        self.points.iter().map(|point| point.node_id).collect()
+    }
+
+    pub fn length(&self) -> f64 {
+        self.legs.iter().sum()
+    }
+
+    // Percentage of the distance ridden on dedicated cycleways
+    pub fn cycleway_share(&self) -> f64 {
+        let length = self.length();
+        if length == 0.0 {
+            return 0.0;
+        }
+        let cycleway_length: f64 = self
+            .points
+            .iter()
+            .filter(|point| point.cycleway)
+            .map(|point| point.length)
+            .sum();
+        cycleway_length / length * 100.0
+    }
+
+    // Average of the cyclability scores weighted by the length of the scored edges
+    pub fn average_score(&self) -> Option<f64> {
+        let (scored_length, weighted_score) = self
+            .points
+            .iter()
+            .filter_map(|point| point.score.map(|score| (point.length, score)))
+            .fold((0.0, 0.0), |(length, weighted), (point_length, score)| {
+                (length + point_length, weighted + point_length * score)
+            });
+        if scored_length == 0.0 {
+            return None;
+        }
+        Some(weighted_score / scored_length)
+    }
+
+    fn elevation_differences(&self) -> Vec<f64> {
+        let elevations: Vec<f64> = self
+            .points
+            .iter()
+            .filter_map(|point| point.elevation)
+            .collect();
+        elevations.windows(2).map(|z| z[1] - z[0]).collect()
+    }
+
+    // Total climb in meters
+    pub fn climb(&self) -> f64 {
+        self.elevation_differences()
+            .iter()
+            .filter(|difference| **difference > 0.0)
+            .sum()
+    }
+
+    // Total descent in meters
+    pub fn descent(&self) -> f64 {
+        -self
+            .elevation_differences()
+            .iter()
+            .filter(|difference| **difference < 0.0)
+            .sum::<f64>()
+    }
+
+    // Estimated ride time in minutes
+    pub fn duration(&self) -> f64 {
+        (self.length() / 1000.0 / RIDE_SPEED + self.climb() / CLIMB_RATE) * 60.0
+    }
+
+    // The elevation along the route as the points of a svg polyline
+    // in a 300 by 60 box. Empty when there is no elevation model.
+    pub fn elevation_profile(&self) -> String {
+        let mut distance = 0.0;
+        let mut profile: Vec<(f64, f64)> = vec![];
+        for point in self.points.iter() {
+            if let Some(elevation) = point.elevation {
+                profile.push((distance, elevation));
+            }
+            distance += point.length;
+        }
+        if profile.len() < 2 || distance == 0.0 {
+            return "".to_string();
+        }
+        let min = profile
+            .iter()
+            .map(|(_, z)| *z)
+            .fold(f64::INFINITY, f64::min);
+        let max = profile
+            .iter()
+            .map(|(_, z)| *z)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let height = (max - min).max(1.0);
+        profile
+            .iter()
+            .map(|(d, z)| {
+                format!(
+                    "{:.1},{:.1}",
+                    d / distance * 300.0,
+                    60.0 - (z - min) / height * 60.0
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RouteOptions {
+    pub kind: Option<RouteKind>,
+    // name of the routing profile
+    pub profile: Option<String>,
+    // direction in degrees of the first half of a loop
+    pub bearing: Option<f64>,
+    // local time like 2024-06-10T08:30, now when missing
+    pub departure: Option<String>,
+}
 
 pub async fn route(
     State(state): State<VeloinfoState>,
     Path((start_lng, start_lat, end_lng, end_lat)): Path<(f64, f64, f64, f64)>,
+    Query(options): Query<RouteOptions>,
+) -> RoutePanel {
+    route_panel(
+        &state,
+        &[(start_lng, start_lat), (end_lng, end_lat)],
+        format!("/route/{start_lng}/{start_lat}/{end_lng}/{end_lat}"),
+        &options,
+    )
+    .await
+}
+
+// The waypoints are given as "lng,lat;lng,lat;..." in the order they are visited.
+pub async fn route_via(
+    State(state): State<VeloinfoState>,
+    Path(waypoints): Path<String>,
+    Query(options): Query<RouteOptions>,
+) -> Result<RoutePanel, (StatusCode, String)> {
+    let export_path = format!("/route/via/{waypoints}");
+    let waypoints = parse_waypoints(&waypoints).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(route_panel(&state, &waypoints, export_path, &options).await)
+}
+
+// A loop of about `distance` km starting and ending at the same point
+pub async fn route_loop(
+    State(state): State<VeloinfoState>,
+    Path((lng, lat, distance)): Path<(f64, f64, f64)>,
+    Query(options): Query<RouteOptions>,
 ) -> RoutePanel {
-    let start = match Edge::find_closest_node(&start_lng, &start_lat, &state.conn).await {
-        Ok(start) => start,
+    let waypoints = match loop_waypoints(&state, (lng, lat), distance * 1000.0, &options).await {
+        Ok(waypoints) => waypoints,
+        Err(e) => return RoutePanel::error(e),
+    };
+    // the waypoints are random, the exports must use the resolved ones
+    let export_path = format!("/route/via/{}", format_waypoints(&waypoints));
+    route_panel(&state, &waypoints, export_path, &options).await
+}
+
+pub async fn route_loop_geojson(
+    State(state): State<VeloinfoState>,
+    Path((lng, lat, distance)): Path<(f64, f64, f64)>,
+    Query(options): Query<RouteOptions>,
+) -> Result<(HeaderMap, Json<Value>), (StatusCode, String)> {
+    if !valid_loop_distance(distance * 1000.0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "The distance of the loop must be between 0 and {} km",
+                MAX_LOOP_DISTANCE / 1000.0
+            ),
+        ));
+    }
+    let waypoints = loop_waypoints(&state, (lng, lat), distance * 1000.0, &options)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e))?;
+    geojson_response(&state, &waypoints, &options).await
+}
+
+pub async fn route_share(
+    State(state): State<VeloinfoState>,
+    Path((start_lng, start_lat, end_lng, end_lat)): Path<(f64, f64, f64, f64)>,
+    Query(options): Query<RouteOptions>,
+) -> RouteShare {
+    share(
+        &state,
+        &[(start_lng, start_lat), (end_lng, end_lat)],
+        &options,
+    )
+    .await
+}
+
+pub async fn route_via_share(
+    State(state): State<VeloinfoState>,
+    Path(waypoints): Path<String>,
+    Query(options): Query<RouteOptions>,
+) -> Result<RouteShare, (StatusCode, String)> {
+    let waypoints = parse_waypoints(&waypoints).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(share(&state, &waypoints, &options).await)
+}
+
+// The shared link opens the map when it is not loaded by htmx. The route is
+// computed again and compared with the one that was shared because the
+// edges change with every import and every new score.
+pub async fn route_share_get(
+    State(state): State<VeloinfoState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if !is_share_id(&id) {
+        return (StatusCode::NOT_FOUND, "Cet itinéraire n'existe pas").into_response();
+    }
+    if !headers.contains_key("hx-request") {
+        return Redirect::to(&format!("/?route={id}")).into_response();
+    }
+    let shared = match SharedRoute::get(&id, &state.conn).await {
+        Ok(shared) => shared,
         Err(e) => {
-            return RoutePanel {
-                route_json: "[]".to_string(),
-                total_length: 0.0,
-                error: format!("Error while fetching start node: {}", e),
-            };
+            eprintln!("Error while fetching the shared route {}: {}", id, e);
+            return RoutePanel::error("Cet itinéraire n'existe pas".to_string()).into_response();
         }
     };
-    let end = match Edge::find_closest_node(&end_lng, &end_lat, &state.conn).await {
-        Ok(end) => end,
+    let options = RouteOptions {
+        kind: RouteKind::all()
+            .into_iter()
+            .find(|kind| kind.param() == shared.kind),
+        profile: Some(shared.profile.clone()),
+        bearing: None,
+        departure: None,
+    };
+    let waypoints = match parse_waypoints(&shared.waypoints) {
+        Ok(waypoints) => waypoints,
+        Err(e) => return RoutePanel::error(e).into_response(),
+    };
+    let profile = match get_profile(&state, &options).await {
+        Ok(profile) => profile,
+        Err(e) => return RoutePanel::error(e).into_response(),
+    };
+    let routes = match route_alternatives(&state, &waypoints, &options, &profile).await {
+        Ok(routes) => routes,
         Err(e) => {
-            return RoutePanel {
-                route_json: "[]".to_string(),
-                total_length: 0.0,
-                error: format!("Error while fetching end node: {}", e),
-            };
+            return RoutePanel::error(format!(
+                "Cet itinéraire partagé le {} n'est plus possible: {}",
+                shared.created_at.format("%Y-%m-%d"),
+                e
+            ))
+            .into_response()
         }
     };
-    let mut edges = Edge::route(&start, &end, &state.conn).await;
-    if let 0 = edges.len() {
-        println!("No route found");
-        return RoutePanel {
-            route_json: "[]".to_string(),
-            total_length: 0.0,
-            error: format!("No route found from {start:?} to {end:?}"),
+    let changed = routes[0].1.node_ids() != shared.node_ids;
+    let export_path = format!("/route/via/{}", shared.waypoints);
+    let mut panel = alternatives_panel(&state, &routes, export_path, profile, &options).await;
+    if changed {
+        panel.warning = format!(
+            "Les données ont changé depuis que cet itinéraire a été partagé le {}, il a été recalculé.",
+            shared.created_at.format("%Y-%m-%d")
+        );
+    }
+    panel.into_response()
+}
+
+// Everything reachable in `minutes` of comfortable riding. The less comfortable
+// roads count for more distance, like when routing.
+pub async fn isochrone(
+    State(state): State<VeloinfoState>,
+    Path((lng, lat, minutes)): Path<(f64, f64, f64)>,
+    Query(options): Query<RouteOptions>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    if !minutes.is_finite() || minutes <= 0.0 || minutes > MAX_ISOCHRONE_MINUTES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "The minutes must be between 0 and {}",
+                MAX_ISOCHRONE_MINUTES
+            ),
+        ));
+    }
+    let profile = get_profile(&state, &options)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let start = Edge::find_closest_node(&lng, &lat, &state.conn)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Error while fetching start node: {}", e),
+            )
+        })?;
+    let meters = minutes / 60.0 * RIDE_SPEED * 1000.0;
+    // the cost is computed on lengths in the 3857 projection
+    let budget = meters / lat.to_radians().cos();
+    let geometry = Edge::isochrone(&start, budget, &profile, &state.conn)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error while computing the isochrone: {}", e),
+            )
+        })?
+        .ok_or((StatusCode::NOT_FOUND, "Nothing is reachable".to_string()))?;
+    let geometry: Value = serde_json::from_str(&geometry)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(json!({
+        "type": "Feature",
+        "properties": {
+            "minutes": minutes,
+            "profile": profile.name,
+        },
+        "geometry": geometry,
+    })))
+}
+
+pub async fn route_gpx(
+    State(state): State<VeloinfoState>,
+    Path((start_lng, start_lat, end_lng, end_lat)): Path<(f64, f64, f64, f64)>,
+    Query(options): Query<RouteOptions>,
+) -> Result<(HeaderMap, RouteGpx), (StatusCode, String)> {
+    gpx_response(
+        &state,
+        &[(start_lng, start_lat), (end_lng, end_lat)],
+        &options,
+    )
+    .await
+}
+
+pub async fn route_via_gpx(
+    State(state): State<VeloinfoState>,
+    Path(waypoints): Path<String>,
+    Query(options): Query<RouteOptions>,
+) -> Result<(HeaderMap, RouteGpx), (StatusCode, String)> {
+    let waypoints = parse_waypoints(&waypoints).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    gpx_response(&state, &waypoints, &options).await
+}
+
+pub async fn route_geojson(
+    State(state): State<VeloinfoState>,
+    Path((start_lng, start_lat, end_lng, end_lat)): Path<(f64, f64, f64, f64)>,
+    Query(options): Query<RouteOptions>,
+) -> Result<(HeaderMap, Json<Value>), (StatusCode, String)> {
+    geojson_response(
+        &state,
+        &[(start_lng, start_lat), (end_lng, end_lat)],
+        &options,
+    )
+    .await
+}
+
+pub async fn route_via_geojson(
+    State(state): State<VeloinfoState>,
+    Path(waypoints): Path<String>,
+    Query(options): Query<RouteOptions>,
+) -> Result<(HeaderMap, Json<Value>), (StatusCode, String)> {
+    let waypoints = parse_waypoints(&waypoints).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    geojson_response(&state, &waypoints, &options).await
+}
+
+pub async fn route_instructions_get(
+    State(state): State<VeloinfoState>,
+    Path((start_lng, start_lat, end_lng, end_lat)): Path<(f64, f64, f64, f64)>,
+    Query(options): Query<RouteOptions>,
+) -> Result<Json<Vec<Instruction>>, (StatusCode, String)> {
+    instructions_response(
+        &state,
+        &[(start_lng, start_lat), (end_lng, end_lat)],
+        &options,
+    )
+    .await
+}
+
+pub async fn route_via_instructions_get(
+    State(state): State<VeloinfoState>,
+    Path(waypoints): Path<String>,
+    Query(options): Query<RouteOptions>,
+) -> Result<Json<Vec<Instruction>>, (StatusCode, String)> {
+    let waypoints = parse_waypoints(&waypoints).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    instructions_response(&state, &waypoints, &options).await
+}
+
+// Computes one route for each kind of cost model so the rider can pick
+// the trade-off between safety and distance.
+async fn route_panel(
+    state: &VeloinfoState,
+    waypoints: &[(f64, f64)],
+    export_path: String,
+    options: &RouteOptions,
+) -> RoutePanel {
+    let profile = match get_profile(state, options).await {
+        Ok(profile) => profile,
+        Err(e) => return RoutePanel::error(e),
+    };
+    let routes = match route_alternatives(state, waypoints, options, &profile).await {
+        Ok(routes) => routes,
+        Err(e) => return RoutePanel::error(e),
+    };
+    alternatives_panel(state, &routes, export_path, profile, options).await
+}
+
+// The kind asked in the options comes first. The kinds giving the same path
+// as a previous one are dropped.
+pub async fn route_alternatives(
+    state: &VeloinfoState,
+    waypoints: &[(f64, f64)],
+    options: &RouteOptions,
+    profile: &RoutingProfile,
+) -> Result<Vec<(RouteKind, Route)>, String> {
+    let mut kinds = RouteKind::all().to_vec();
+    if let Some(kind) = options.kind {
+        kinds.retain(|other| *other != kind);
+        kinds.insert(0, kind);
+    }
+    let mut routes: Vec<(RouteKind, Route)> = vec![];
+    let mut error = "".to_string();
+    for kind in kinds {
+        match compute_route(state, waypoints, kind, profile, &departure(options)).await {
+            Ok(route) => {
+                let same_path = routes
+                    .iter()
+                    .any(|(_, other)| other.node_ids() == route.node_ids());
+                if !same_path {
+                    routes.push((kind, route));
+                }
+            }
+            Err(e) => error = e,
+        }
+    }
+    if routes.is_empty() {
+        return Err(error);
+    }
+    Ok(routes)
+}
+
+async fn alternatives_panel(
+    state: &VeloinfoState,
+    routes: &[(RouteKind, Route)],
+    export_path: String,
+    profile: RoutingProfile,
+    options: &RouteOptions,
+) -> RoutePanel {
+    let Some((_, route)) = routes.first() else {
+        return RoutePanel::error("No route found".to_string());
+    };
+    let mut alternatives = vec![];
+    for (kind, route) in routes.iter() {
+        let names = route_names(state, route).await;
+        let segments = route_segments(&route.points);
+        let edges_coordinate: Vec<(f64, f64)> =
+            route.points.iter().map(|edge| (edge.x, edge.y)).collect();
+        let route_json = match serde_json::to_string(&edges_coordinate) {
+            Ok(edges_coordinate) => edges_coordinate,
+            Err(e) => {
+                return RoutePanel::error(format!("Error while serializing edges: {}", e));
+            }
         };
+        alternatives.push(RouteAlternative {
+            name: kind.name().to_string(),
+            export_query: export_query(kind, &profile, options),
+            route_json,
+            total_length: to_km(route.length()),
+            cycleway_share: route.cycleway_share().round(),
+            average_score: route
+                .average_score()
+                .map(|score| (score * 100.0).round() / 100.0),
+            instructions: instructions(&route.points, &names),
+            duration: route.duration().round(),
+            climb: route.climb().round(),
+            descent: route.descent().round(),
+            elevation_profile: route.elevation_profile(),
+            categories: category_lengths(&segments),
+            reported: reported_segments(state, &segments, &names).await,
+        });
+    }
+    RoutePanel {
+        route_json: alternatives[0].route_json.clone(),
+        total_length: to_km(route.length()),
+        duration: route.duration().round(),
+        legs: route.legs.iter().map(|leg| to_km(*leg)).collect(),
+        alternatives,
+        error: "".to_string(),
+        warning: "".to_string(),
+        export_path,
+        profile: profile.name,
+        profiles: RoutingProfile::get_all(&state.conn).await,
+        departure: parse_departure(options)
+            .map(|departure| format_departure(&departure))
+            .unwrap_or_default(),
+    }
+}
+
+async fn share(
+    state: &VeloinfoState,
+    waypoints: &[(f64, f64)],
+    options: &RouteOptions,
+) -> RouteShare {
+    let kind = options.kind.unwrap_or_default();
+    let route = match route_with_options(state, waypoints, options).await {
+        Ok(route) => route,
+        Err((_, e)) => return RouteShare::error(e),
+    };
+    let profile = match get_profile(state, options).await {
+        Ok(profile) => profile,
+        Err(e) => return RouteShare::error(e),
+    };
+    let coordinates: Vec<(f64, f64)> = route
+        .points
+        .iter()
+        .map(|point| (point.x, point.y))
+        .collect();
+    let id = share_id();
+    match SharedRoute::insert(
+        &id,
+        &format_waypoints(waypoints),
+        kind.param(),
+        &profile.name,
+        &route.node_ids(),
+        &coordinates,
+        &state.conn,
+    )
+    .await
+    {
+        Ok(_) => RouteShare {
+            path: format!("/route/share/{id}"),
+            error: "".to_string(),
+        },
+        Err(e) => RouteShare::error(format!("Error while sharing the route: {}", e)),
+    }
+}
+
+// 8 base62 characters from the random bits of a uuid
+fn share_id() -> String {
+    const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let mut random = Uuid::now_v7().as_u128() as u64;
+    (0..8)
+        .map(|_| {
+            let c = ALPHABET[(random % 62) as usize] as char;
+            random /= 62;
+            c
+        })
+        .collect()
+}
+
+// the shape of the ids made by share_id
+fn is_share_id(id: &str) -> bool {
+    id.len() == 8 && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+async fn gpx_response(
+    state: &VeloinfoState,
+    waypoints: &[(f64, f64)],
+    options: &RouteOptions,
+) -> Result<(HeaderMap, RouteGpx), (StatusCode, String)> {
+    let route = route_with_options(state, waypoints, options).await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "Content-Type",
+        HeaderValue::from_static("application/gpx+xml"),
+    );
+    headers.insert(
+        "Content-Disposition",
+        HeaderValue::from_static("attachment; filename=\"veloinfo.gpx\""),
+    );
+    Ok((
+        headers,
+        RouteGpx {
+            name: "Itinéraire veloinfo".to_string(),
+            points: route.points,
+        },
+    ))
+}
+
+async fn geojson_response(
+    state: &VeloinfoState,
+    waypoints: &[(f64, f64)],
+    options: &RouteOptions,
+) -> Result<(HeaderMap, Json<Value>), (StatusCode, String)> {
+    let route = route_with_options(state, waypoints, options).await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "Content-Disposition",
+        HeaderValue::from_static("attachment; filename=\"veloinfo.geojson\""),
+    );
+    Ok((headers, Json(route_geojson_value(&route.points))))
+}
+
+async fn instructions_response(
+    state: &VeloinfoState,
+    waypoints: &[(f64, f64)],
+    options: &RouteOptions,
+) -> Result<Json<Vec<Instruction>>, (StatusCode, String)> {
+    let route = route_with_options(state, waypoints, options).await?;
+    Ok(Json(route_instructions(state, &route).await))
+}
+
+async fn route_with_options(
+    state: &VeloinfoState,
+    waypoints: &[(f64, f64)],
+    options: &RouteOptions,
+) -> Result<Route, (StatusCode, String)> {
+    let profile = get_profile(state, options)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    compute_route(
+        state,
+        waypoints,
+        options.kind.unwrap_or_default(),
+        &profile,
+        &departure(options),
+    )
+    .await
+    .map_err(|e| (StatusCode::NOT_FOUND, e))
+}
+
+pub async fn route_instructions(state: &VeloinfoState, route: &Route) -> Vec<Instruction> {
+    let names = route_names(state, route).await;
+    instructions(&route.points, &names)
+}
+
+async fn route_names(state: &VeloinfoState, route: &Route) -> HashMap<i64, String> {
+    let mut way_ids: Vec<i64> = route.points.iter().map(|point| point.way_id).collect();
+    way_ids.sort();
+    way_ids.dedup();
+    Cycleway::get_names(&way_ids, &state.conn).await
+}
+
+// Meters of the route in each category of score, the ways without score last
+fn category_lengths(segments: &[RouteSegment]) -> Vec<CategoryLength> {
+    [
+        Some(Category::Good),
+        Some(Category::Problems),
+        Some(Category::Dangerous),
+        Some(Category::Closed),
+        None,
+    ]
+    .into_iter()
+    .map(|category| CategoryLength {
+        label: category
+            .as_ref()
+            .map(|category| category.label())
+            .unwrap_or("Inconnu")
+            .to_string(),
+        length: segments
+            .iter()
+            .filter(|segment| segment.score.map(Category::from_score) == category)
+            .map(|segment| segment.length)
+            .sum::<f64>()
+            .round(),
+    })
+    .collect()
+}
+
+async fn reported_segments(
+    state: &VeloinfoState,
+    segments: &[RouteSegment],
+    names: &HashMap<i64, String>,
+) -> Vec<ReportedSegment> {
+    let segments: Vec<(&RouteSegment, Category)> = segments
+        .iter()
+        .filter_map(|segment| Some((segment, Category::from_score(segment.score?))))
+        .filter(|(_, category)| *category == Category::Dangerous || *category == Category::Closed)
+        .collect();
+    if segments.is_empty() {
+        return vec![];
+    }
+    let way_ids = segments.iter().map(|(segment, _)| segment.way_id).collect();
+    let score_ids = CyclabilityScore::get_last_ids(&way_ids, &state.conn).await;
+    let mut reported: Vec<ReportedSegment> = vec![];
+    for (segment, category) in segments {
+        let Some(score_id) = score_ids.get(&segment.way_id) else {
+            continue;
+        };
+        // a way can be ridden twice, like on a loop
+        if let Some(other) = reported
+            .iter_mut()
+            .find(|other| other.score_id == *score_id)
+        {
+            other.length += segment.length.round();
+            continue;
+        }
+        reported.push(ReportedSegment {
+            score_id: *score_id,
+            name: names
+                .get(&segment.way_id)
+                .cloned()
+                .unwrap_or("Segment sans nom".to_string()),
+            label: category.label().to_string(),
+            length: segment.length.round(),
+        });
+    }
+    reported
+}
+
+pub async fn get_profile(
+    state: &VeloinfoState,
+    options: &RouteOptions,
+) -> Result<RoutingProfile, String> {
+    let name = options.profile.clone().unwrap_or("commuter".to_string());
+    RoutingProfile::get_by_name(&name, &state.conn)
+        .await
+        .map_err(|e| format!("Error while fetching the routing profile: {}", e))
+}
+
+// The query string to get the same route from the export urls
+fn export_query(kind: &RouteKind, profile: &RoutingProfile, options: &RouteOptions) -> String {
+    let query = format!("?kind={}&profile={}", kind.param(), profile.name);
+    match parse_departure(options) {
+        Some(departure) => format!("{query}&departure={}", format_departure(&departure)),
+        None => query,
+    }
+}
+
+fn departure(options: &RouteOptions) -> DateTime<Local> {
+    parse_departure(options).unwrap_or(Local::now())
+}
+
+fn parse_departure(options: &RouteOptions) -> Option<DateTime<Local>> {
+    let departure = options.departure.as_ref()?;
+    let departure = NaiveDateTime::parse_from_str(departure, "%Y-%m-%dT%H:%M").ok()?;
+    Local.from_local_datetime(&departure).earliest()
+}
+
+// The format of the datetime-local inputs
+fn format_departure(departure: &DateTime<Local>) -> String {
+    departure.format("%Y-%m-%dT%H:%M").to_string()
+}
+
+// The loop goes through two waypoints making a triangle with the start.
+// The waypoints are moved on the nearest well scored cycleway.
+async fn loop_waypoints(
+    state: &VeloinfoState,
+    start: (f64, f64),
+    distance: f64,
+    options: &RouteOptions,
+) -> Result<Vec<(f64, f64)>, String> {
+    if !valid_loop_distance(distance) {
+        return Err(format!(
+            "The distance of the loop must be between 0 and {} km",
+            MAX_LOOP_DISTANCE / 1000.0
+        ));
+    }
+    let bearing = options
+        .bearing
+        .unwrap_or((Local::now().timestamp_subsec_nanos() % 360) as f64);
+    let profile = get_profile(state, options).await?;
+    // a routed path is about 30% longer than the straight line
+    let mut side = distance / 3.0 / 1.3;
+    let mut waypoints = vec![];
+    // one more try with a corrected size when the loop is too short or too long
+    for _ in 0..2 {
+        waypoints = vec![start];
+        for angle in [bearing, bearing + 60.0] {
+            let (lng, lat) = destination(start, side, angle);
+            let waypoint = Cycleway::find_best_near(&lng, &lat, &(side / 2.0), &state.conn)
+                .await
+                .unwrap_or((lng, lat));
+            waypoints.push(waypoint);
+        }
+        waypoints.push(start);
+        let route = compute_route(
+            state,
+            &waypoints,
+            options.kind.unwrap_or_default(),
+            &profile,
+            &departure(options),
+        )
+        .await?;
+        // the start is on a dead end, the loop can't be resized
+        if route.length() <= 0.0 {
+            return Err("No loop from this point".to_string());
+        }
+        let ratio = distance / route.length();
+        if (0.8..1.25).contains(&ratio) {
+            break;
+        }
+        side *= ratio;
+    }
+    Ok(waypoints)
+}
+
+fn valid_loop_distance(distance: f64) -> bool {
+    distance.is_finite() && distance > 0.0 && distance <= MAX_LOOP_DISTANCE
+}
+
+// The point at `distance` meters from `start` in the direction of `bearing`
+fn destination((lng, lat): (f64, f64), distance: f64, bearing: f64) -> (f64, f64) {
+    let meters_per_degree = 111_320.0;
+    (
+        lng + distance * bearing.to_radians().sin() / (meters_per_degree * lat.to_radians().cos()),
+        lat + distance * bearing.to_radians().cos() / meters_per_degree,
+    )
+}
+
+fn format_waypoints(waypoints: &[(f64, f64)]) -> String {
+    waypoints
+        .iter()
+        .map(|(lng, lat)| format!("{lng},{lat}"))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+// "lng,lat;lng,lat;..." A malformed waypoint is an error, never skipped,
+// a typo in a shared url must not become another route.
+pub fn parse_waypoints(waypoints: &str) -> Result<Vec<(f64, f64)>, String> {
+    waypoints
+        .split(';')
+        .map(|waypoint| {
+            let error = || format!("Point invalide : {}", waypoint);
+            let (lng, lat) = waypoint.split_once(',').ok_or_else(error)?;
+            let lng = lng.trim().parse::<f64>().map_err(|_| error())?;
+            let lat = lat.trim().parse::<f64>().map_err(|_| error())?;
+            if !(-180.0..=180.0).contains(&lng) || !(-90.0..=90.0).contains(&lat) {
+                return Err(error());
+            }
+            Ok((lng, lat))
+        })
+        .collect()
+}
+
+fn to_km(meters: f64) -> f64 {
+    (meters / 10.0).round() / 100.0
+}
+
+// Route through every waypoint in order. Each leg is routed on its own
+// and the legs are stitched together at the waypoints.
+async fn compute_route(
+    state: &VeloinfoState,
+    waypoints: &[(f64, f64)],
+    kind: RouteKind,
+    profile: &RoutingProfile,
+    departure: &DateTime<Local>,
+) -> Result<Route, String> {
+    if waypoints.len() < 2 {
+        return Err("At least a start and an end are needed".to_string());
+    }
+    let mut route = Route {
+        points: vec![],
+        legs: vec![],
+    };
+    for (i, leg) in waypoints.windows(2).enumerate() {
+        let mut points = compute_leg(state, leg[0], leg[1], kind, profile, departure)
+            .await
+            .map_err(|e| format!("Leg {}: {}", i + 1, e))?;
+        route
+            .legs
+            .push(points.iter().map(|point| point.length).sum());
+        if !route.points.is_empty() {
+            // the waypoint is already the last point of the previous leg
+            points.remove(0);
+        }
+        route.points.append(&mut points);
+    }
+    Ok(route)
+}
+
+async fn compute_leg(
+    state: &VeloinfoState,
+    (start_lng, start_lat): (f64, f64),
+    (end_lng, end_lat): (f64, f64),
+    kind: RouteKind,
+    profile: &RoutingProfile,
+    departure: &DateTime<Local>,
+) -> Result<Vec<Point>, String> {
+    let start = Edge::find_closest_node(&start_lng, &start_lat, &state.conn)
+        .await
+        .map_err(|e| format!("Error while fetching start node: {}", e))?;
+    let end = Edge::find_closest_node(&end_lng, &end_lat, &state.conn)
+        .await
+        .map_err(|e| format!("Error while fetching end node: {}", e))?;
+    let mut edges = match graph::get()
+        .and_then(|graph| graph.route(start.node_id, end.node_id, kind, profile, departure))
+    {
+        Some(edges) => edges,
+        None => Edge::route(&start, &end, kind, profile, departure, &state.conn).await,
+    };
+    if edges.is_empty() {
+        println!("No route found");
+        return Err(format!("No route found from {start:?} to {end:?}"));
     };
     edges.insert(
         0,
@@ -44,6 +919,9 @@ pub async fn route(
             length: 0.0,
             way_id: 0,
             node_id: 0,
+            score: None,
+            cycleway: false,
+            elevation: None,
         },
     );
     edges.push(Point {
@@ -52,22 +930,93 @@ pub async fn route(
         length: 0.0,
         way_id: 0,
         node_id: 0,
+        score: None,
+        cycleway: false,
+        elevation: None,
     });
-    let edges_coordinate: Vec<(f64, f64)> = edges.iter().map(|edge| (edge.x, edge.y)).collect();
-    let total_length: f64 = edges.iter().map(|edge| edge.length).sum();
-    let route_json = match serde_json::to_string(&edges_coordinate) {
-        Ok(edges_coordinate) => edges_coordinate,
-        Err(e) => {
-            return RoutePanel {
-                route_json: "[]".to_string(),
-                total_length: 0.0,
-                error: format!("Error while serializing edges: {}", e),
-            };
+    Ok(edges)
+}
+
+// A segment is a run of consecutive points on the same way.
+pub struct RouteSegment {
+    pub way_id: i64,
+    pub length: f64,
+    pub score: Option<f64>,
+    pub cycleway: bool,
+    pub coordinates: Vec<[f64; 2]>,
+}
+
+pub fn route_segments(points: &[Point]) -> Vec<RouteSegment> {
+    let mut segments: Vec<RouteSegment> = vec![];
+    for (i, point) in points.iter().enumerate() {
+        let next = points.get(i + 1);
+        match segments.last_mut() {
+            Some(segment) if segment.way_id == point.way_id => {
+                segment.length += point.length;
+            }
+            _ => segments.push(RouteSegment {
+                way_id: point.way_id,
+                length: point.length,
+                score: point.score,
+                cycleway: point.cycleway,
+                coordinates: vec![[point.x, point.y]],
+            }),
         }
-    };
-    RoutePanel {
-        route_json,
-        total_length: (total_length / 10.0).round() / 100.0,
-        error: "".to_string(),
+        if let (Some(segment), Some(next)) = (segments.last_mut(), next) {
+            segment.coordinates.push([next.x, next.y]);
+        }
+    }
+    segments.retain(|segment| segment.coordinates.len() > 1);
+    segments
+}
+
+pub fn route_geojson_value(points: &[Point]) -> Value {
+    let features: Vec<Value> = route_segments(points)
+        .iter()
+        .map(|segment| {
+            json!({
+                "type": "Feature",
+                "properties": {
+                    "way_id": segment.way_id,
+                    "length": segment.length,
+                    "score": segment.score,
+                    "cycleway": segment.cycleway,
+                },
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": segment.coordinates,
+                }
+            })
+        })
+        .collect();
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_waypoints_in_order() {
+        assert_eq!(
+            parse_waypoints("-73.5,45.5;-73.6,45.52;-73.7,45.6"),
+            Ok(vec![(-73.5, 45.5), (-73.6, 45.52), (-73.7, 45.6)])
+        );
+    }
+
+    #[test]
+    fn parse_waypoints_rejects_malformed() {
+        // an odd number of coordinates
+        assert!(parse_waypoints("1,2,3,4").is_err());
+        assert!(parse_waypoints("-73.5,45.5;-73.6").is_err());
+        assert!(parse_waypoints("-73.5,45.5;;-73.6,45.52").is_err());
+        assert!(parse_waypoints("-73.5,45.5;-73.6,abc").is_err());
+        assert!(parse_waypoints("-73.5,45.5;-73.6,45.5x").is_err());
+        assert!(parse_waypoints("").is_err());
+        assert!(parse_waypoints("NaN,45.5").is_err());
+        assert!(parse_waypoints("-273.5,45.5").is_err());
     }
 }
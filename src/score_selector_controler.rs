@@ -1,12 +1,24 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, RawQuery, State};
 use axum::Json;
 
-use crate::component::score_selector::ScoreSelector;
+use crate::component::score_selector::{Issue, ScoreSelector};
 use crate::db::cycleway::Cycleway;
 use crate::VeloinfoState;
 
-pub async fn score_selector_controler(Path(score): Path<f64>) -> ScoreSelector {
-    let score_selector = ScoreSelector::get_score_selector(score);
+// The checked issues are kept when the category changes
+pub async fn score_selector_controler(
+    Path(score): Path<f64>,
+    RawQuery(query): RawQuery,
+) -> ScoreSelector {
+    let issues = query
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(key, _)| *key == "issues")
+        .filter_map(|(_, value)| Issue::from_value(value))
+        .map(|issue| issue.value().to_string())
+        .collect::<Vec<String>>();
+    let score_selector = ScoreSelector::get_score_selector(score, &issues);
     score_selector
 }
 
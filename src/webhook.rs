@@ -0,0 +1,131 @@
+use crate::component::score_selector::Category;
+use crate::db::cyclability_score::CyclabilityScore;
+use crate::db::webhook::WebhookDelivery;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::env;
+use std::time::Duration;
+
+lazy_static! {
+    static ref VELOINFO_URL: String = env::var("VELOINFO_URL").expect("VELOINFO_URL must be set");
+}
+
+pub const SCORE_CREATED: &str = "cyclability_score.created";
+pub const SCORE_UPDATED: &str = "cyclability_score.updated";
+
+// Queues the new or edited score for the webhooks whose filters match it,
+// the worker posts it later so the rider doesn't wait for the partners.
+pub async fn enqueue(score_id: i32, event: &str, conn: &PgPool) {
+    let score = match CyclabilityScore::get_by_id(score_id, conn).await {
+        Ok(score) => score,
+        Err(e) => {
+            eprintln!(
+                "Error while getting the score {} of the webhooks: {}",
+                score_id, e
+            );
+            return;
+        }
+    };
+    let category = Category::from_score(score.score).value();
+    let payload = json!({
+        "event": event,
+        "id": score.id,
+        "category": category,
+        "score": score.score,
+        "name": score.name,
+        "comment": score.comment,
+        "issues": score.issues,
+        "way_ids": score.way_ids,
+        "created_at": score.created_at,
+        "url": format!("{}/?score={}", VELOINFO_URL.as_str(), score.id),
+    });
+    if let Err(e) = WebhookDelivery::enqueue(score_id, category, &payload, conn).await {
+        eprintln!("Error while queuing the webhooks of {}: {}", score_id, e);
+    }
+}
+
+// Posts the due deliveries every 30 seconds
+pub async fn worker(conn: PgPool) {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap();
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let deliveries = match WebhookDelivery::get_due(&conn).await {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                eprintln!("Error while getting the webhook deliveries: {}", e);
+                continue;
+            }
+        };
+        for delivery in deliveries {
+            let result = match deliver(&client, &delivery).await {
+                Ok(()) => delivery.set_delivered(&conn).await,
+                Err(error) => {
+                    eprintln!(
+                        "Webhook delivery {} to {} failed: {}",
+                        delivery.id, delivery.url, error
+                    );
+                    delivery.set_failed(&error, &conn).await
+                }
+            };
+            if let Err(e) = result {
+                eprintln!(
+                    "Error while updating the webhook delivery {}: {}",
+                    delivery.id, e
+                );
+            }
+        }
+    }
+}
+
+// The hex HMAC-SHA256 of the body with the secret of the webhook
+fn sign(secret: &str, body: &str) -> Result<String, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|e| e.to_string())?;
+    mac.update(body.as_bytes());
+    Ok(mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>())
+}
+
+// The body is signed in X-Veloinfo-Signature: sha256=<hex>
+async fn deliver(client: &reqwest::Client, delivery: &WebhookDelivery) -> Result<(), String> {
+    let body = delivery.payload.to_string();
+    let signature = sign(&delivery.secret, &body)?;
+
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-Veloinfo-Delivery", delivery.id.to_string())
+        .header("X-Veloinfo-Signature", format!("sha256={}", signature))
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("status {}", response.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sign;
+
+    // RFC 4231, test case 2
+    #[test]
+    fn sign_known_vector() {
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?").unwrap(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
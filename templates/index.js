@@ -41,8 +41,18 @@ map.addControl(new maplibregl.GeolocateControl({
 }));
 
 map.on("load", () => {
+    // a shared route
+    if (params.has("route")) {
+        htmx.ajax("GET", "/route/share/" + params.get("route"), "#info");
+        return;
+    }
+    // a contribution from a digest
+    if (params.has("score")) {
+        htmx.ajax("GET", "/segment_panel/id/" + params.get("score"), "#info");
+        return;
+    }
     const bounds = map.getBounds();
-    htmx.ajax("GET", "/info_panel/up/" + bounds._sw.lng + "/" + bounds._sw.lat + "/" + bounds._ne.lng + "/" + bounds._ne.lat, "#info");
+    htmx.ajax("GET", "/info_panel/up/" + bounds._sw.lng + "/" + bounds._sw.lat + "/" + bounds._ne.lng + "/" + bounds._ne.lat + issue_filter(), "#info");
 })
 
 
@@ -63,6 +73,7 @@ map.on("move", function (e) {
 
 let start_marker = null;
 let end_marker = null;
+let via_markers = [];
 async function select(event) {
     const segment_panel_bigger = document.getElementById("segment_panel_bigger");
     if (segment_panel_bigger) {
@@ -153,6 +164,11 @@ function display_segment_geom(geom) {
 }
 
 
+// The tiles of the hazards are reloaded after a new one
+function refresh_hazards() {
+    map.getSource("veloinfo_hazard").setUrl("{{martin_url}}/hazard");
+}
+
 let timeout_url = null;
 function update_url() {
     if (timeout_url) {
@@ -168,11 +184,16 @@ function update_url() {
         localStorage.setItem("position", JSON.stringify(position));
         if (document.getElementById("info_panel_up")) {
             const bounds = map.getBounds();
-            htmx.ajax("GET", "/info_panel/up/" + bounds._sw.lng + "/" + bounds._sw.lat + "/" + bounds._ne.lng + "/" + bounds._ne.lat, "#info");
+            htmx.ajax("GET", "/info_panel/up/" + bounds._sw.lng + "/" + bounds._sw.lat + "/" + bounds._ne.lng + "/" + bounds._ne.lat + issue_filter(), "#info");
         }
     }, 1000);
 }
 
+// The contributions of the info panel can be limited to one issue
+function issue_filter() {
+    return "?issue=" + (localStorage.getItem("issue") || "");
+}
+
 async function clear() {
     if (start_marker) {
         start_marker.remove();
@@ -180,6 +201,15 @@ async function clear() {
     if (end_marker) {
         end_marker.remove();
     }
+    via_markers.forEach((marker) => marker.remove());
+    via_markers = [];
+    const isochrone = map.getSource("isochrone");
+    if (isochrone) {
+        isochrone.setData({
+            "type": "FeatureCollection",
+            "features": []
+        });
+    }
     const selected = map.getSource("selected");
     if (selected) {
         selected.setData({
@@ -205,7 +235,63 @@ async function route() {
             resolve(position);
         });
     });
-    await htmx.ajax("GET", "/route/" + start.coords.longitude + "/" + start.coords.latitude + "/" + end.lng + "/" + end.lat, "#info");
+    const profile = "?profile=" + (localStorage.getItem("profile") || "commuter");
+    if (via_markers.length) {
+        var waypoints = [[start.coords.longitude, start.coords.latitude]]
+            .concat(via_markers.map((marker) => [marker.getLngLat().lng, marker.getLngLat().lat]))
+            .concat([[end.lng, end.lat]]);
+        await htmx.ajax("GET", "/route/via/" + waypoints.map((waypoint) => waypoint.join(",")).join(";") + profile, "#info");
+        return;
+    }
+    await htmx.ajax("GET", "/route/" + start.coords.longitude + "/" + start.coords.latitude + "/" + end.lng + "/" + end.lat + profile, "#info");
+}
+
+// A loop starting and ending at the selected point
+async function loop_route() {
+    const start = start_marker.getLngLat();
+    const distance = document.getElementById("loop_distance").value;
+    const profile = "?profile=" + (localStorage.getItem("profile") || "commuter");
+    await htmx.ajax("GET", "/route/loop/" + start.lng + "/" + start.lat + "/" + distance + profile, "#info");
+}
+
+// The area reachable from the selected point in a few minutes
+async function show_isochrone() {
+    const start = start_marker.getLngLat();
+    const minutes = document.getElementById("isochrone_minutes").value;
+    const profile = "?profile=" + (localStorage.getItem("profile") || "commuter");
+    const response = await fetch("/isochrone/" + start.lng + "/" + start.lat + "/" + minutes + profile);
+    if (!response.ok) {
+        console.log(await response.text());
+        return;
+    }
+    const isochrone = await response.json();
+    if (map.getSource("isochrone")) {
+        map.getSource("isochrone").setData(isochrone);
+    } else {
+        map.addSource("isochrone", {
+            "type": "geojson",
+            "data": isochrone
+        });
+        map.addLayer({
+            "id": "isochrone",
+            "type": "fill",
+            "source": "isochrone",
+            "paint": {
+                "fill-color": "hsl(205, 100%, 50%)",
+                "fill-opacity": 0.2
+            }
+        },
+            "Road labels");
+    }
+}
+
+// The selected point becomes a stop on the next route
+function add_via() {
+    var via = start_marker.getLngLat();
+    via_markers.push(new maplibregl.Marker({ color: "#f80" }).setLngLat([via.lng, via.lat]).addTo(map));
+    start_marker.remove();
+    start_marker = null;
+    htmx.ajax("GET", "/info_panel/down", "#info");
 }
 
 function fitBounds(geom) {